@@ -58,6 +58,50 @@ pub struct Config {
     // Content Security Policy
     pub(crate) csp_report_only: bool,
 
+    // The source browser serves a "temporarily unavailable" page while this file exists, so
+    // operators can turn it off without a redeploy
+    pub(crate) source_browser_disable_path: PathBuf,
+    // The extensions of the source files which are highlighted as their language, everything
+    // else is shown as plain text. All known languages are highlighted if unset.
+    pub(crate) source_rendered_extensions: Option<Vec<String>>,
+    // The kinds of source files which are described on a placeholder page instead of being
+    // served: `binary`, `oversized`, `unsupported` (text that isn't UTF-8) and `large` (text
+    // larger than `source_render_threshold`)
+    pub(crate) source_placeholders: Vec<String>,
+    // The extensions of executable scripts, which are always shown as plain text with a note,
+    // regardless of `source_rendered_extensions` and `?lang=`
+    pub(crate) source_script_extensions: Vec<String>,
+    // Link source files to the issues of their repository mentioning them. `{repository}` is
+    // replaced with the repository URL of the crate, `{path}` with the URL-encoded path of the
+    // file, e.g. `{repository}/issues?q={path}`
+    pub(crate) source_issue_link_template: Option<String>,
+    // Allow `?debug=files-json` on source pages, which dumps the stored file list of a release
+    pub(crate) source_browser_debug: bool,
+    // Highlight source files on the server instead of in the browser, for the languages the
+    // server knows. Files larger than `web::highlight::MAX_HIGHLIGHT_SIZE` are still highlighted
+    // in the browser, files taking longer than `web::highlight::HIGHLIGHT_BUDGET` to highlight
+    // are shown as plain text.
+    pub(crate) source_server_highlighting: bool,
+    // How many source files, and how many bytes of them, are kept in memory after being fetched
+    // from storage, so popular files aren't fetched again on every view. Zero disables the cache.
+    pub(crate) source_file_cache_capacity: usize,
+    pub(crate) source_file_cache_size: usize,
+    // For how many releases what the source browser derives from their whole source, like the
+    // checksums of their files, is kept in memory. Zero disables the cache.
+    pub(crate) source_release_cache_capacity: usize,
+    // The bearer token operators send to `/crate/:name/:version/source-warm` to fill the caches
+    // above for a release before it's visited. The endpoint doesn't exist if unset.
+    pub(crate) source_warm_token: Option<String>,
+    // Text files larger than this aren't rendered in a page, they're served as they are unless
+    // the `large` placeholder is enabled
+    pub(crate) source_render_threshold: usize,
+    // How much of a text file is embedded in its page, larger files are cut off with a link to
+    // their raw contents
+    pub(crate) source_inline_size_limit: usize,
+    // How many matches a search of the source files of a release returns at most, the ones past
+    // it are only counted
+    pub(crate) source_search_max_matches: usize,
+
     // Build params
     pub(crate) build_attempts: u16,
     pub(crate) rustwide_workspace: PathBuf,
@@ -131,6 +175,31 @@ impl Config {
 
             csp_report_only: env("DOCSRS_CSP_REPORT_ONLY", false)?,
 
+            source_browser_disable_path: env(
+                "DOCSRS_SOURCE_BROWSER_DISABLE_PATH",
+                prefix.join("source-browser.disabled"),
+            )?,
+            source_browser_debug: env("DOCSRS_SOURCE_BROWSER_DEBUG", false)?,
+            source_server_highlighting: env("DOCSRS_SOURCE_SERVER_HIGHLIGHTING", false)?,
+            source_file_cache_capacity: env("DOCSRS_SOURCE_FILE_CACHE_CAPACITY", 1000)?,
+            source_file_cache_size: env("DOCSRS_SOURCE_FILE_CACHE_SIZE", 64 * 1024 * 1024)?,
+            source_release_cache_capacity: env("DOCSRS_SOURCE_RELEASE_CACHE_CAPACITY", 100)?,
+            source_warm_token: maybe_env("DOCSRS_SOURCE_WARM_TOKEN")?,
+            source_render_threshold: env("DOCSRS_SOURCE_RENDER_THRESHOLD", 2 * 1024 * 1024)?,
+            source_inline_size_limit: env("DOCSRS_SOURCE_INLINE_SIZE_LIMIT", 1024 * 1024)?,
+            source_search_max_matches: env("DOCSRS_SOURCE_SEARCH_MAX_MATCHES", 200)?,
+            source_issue_link_template: maybe_env("DOCSRS_SOURCE_ISSUE_LINK_TEMPLATE")?,
+            source_rendered_extensions: maybe_env::<String>("DOCSRS_SOURCE_RENDERED_EXTENSIONS")?
+                .map(|extensions| comma_separated(&extensions)),
+            source_placeholders: comma_separated(&env(
+                "DOCSRS_SOURCE_PLACEHOLDERS",
+                "oversized,unsupported".to_string(),
+            )?),
+            source_script_extensions: comma_separated(&env(
+                "DOCSRS_SOURCE_SCRIPT_EXTENSIONS",
+                "sh,bash,zsh,bat,cmd,ps1,py".to_string(),
+            )?),
+
             local_archive_cache_path: env(
                 "DOCSRS_ARCHIVE_INDEX_CACHE_PATH",
                 prefix.join("archive_cache"),
@@ -148,6 +217,14 @@ impl Config {
     }
 }
 
+/// Splits a comma separated list, ignoring empty items
+fn comma_separated(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|item| item.trim().to_owned())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
 fn env<T>(var: &str, default: T) -> Result<T>
 where
     T: FromStr,
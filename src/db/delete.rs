@@ -101,6 +101,13 @@ fn delete_version_from_database(conn: &mut Client, name: &str, version: &str) ->
             &[&crate_id, &version],
         )?;
     }
+    // remember the release, so it can be reported as gone instead of as never having existed
+    transaction.execute(
+        "INSERT INTO deleted_releases (name, version)
+            SELECT $2, version FROM releases WHERE crate_id = $1 AND version = $3
+            ON CONFLICT DO NOTHING",
+        &[&crate_id, &name, &version],
+    )?;
     transaction.execute(
         "DELETE FROM releases WHERE crate_id = $1 AND version = $2",
         &[&crate_id, &version],
@@ -142,6 +149,13 @@ fn delete_crate_from_database(conn: &mut Client, name: &str, crate_id: i32) -> R
         )?;
     }
     transaction.execute("DELETE FROM owner_rels WHERE cid = $1;", &[&crate_id])?;
+    // remember the releases, so they can be reported as gone instead of as never having existed
+    transaction.execute(
+        "INSERT INTO deleted_releases (name, version)
+            SELECT $2, version FROM releases WHERE crate_id = $1
+            ON CONFLICT DO NOTHING",
+        &[&crate_id, &name],
+    )?;
     transaction.execute("DELETE FROM releases WHERE crate_id = $1;", &[&crate_id])?;
     transaction.execute("DELETE FROM crates WHERE id = $1;", &[&crate_id])?;
 
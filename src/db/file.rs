@@ -11,9 +11,10 @@ use crate::error::Result;
 use crate::storage::{CompressionAlgorithm, CompressionAlgorithms, Storage};
 
 use serde_json::Value;
+use std::fs;
 use std::path::{Path, PathBuf};
 
-/// Store all files in a directory and return [[mimetype, filename]] as Json
+/// Store all files in a directory and return [[mimetype, filename, size]] as Json
 ///
 /// If there is an S3 Client configured, store files into an S3 bucket;
 /// otherwise, stores files into the 'files' table of the local database.
@@ -29,7 +30,7 @@ pub fn add_path_into_database<P: AsRef<Path>>(
 ) -> Result<(Value, CompressionAlgorithms)> {
     let (file_list, algorithms) = storage.store_all(prefix.as_ref(), path.as_ref())?;
     Ok((
-        file_list_to_json(file_list.into_iter().collect()),
+        file_list_to_json(path.as_ref(), file_list.into_iter().collect()),
         algorithms,
     ))
 }
@@ -41,20 +42,26 @@ pub fn add_path_into_remote_archive<P: AsRef<Path>>(
 ) -> Result<(Value, CompressionAlgorithm)> {
     let (file_list, algorithm) = storage.store_all_in_archive(archive_path, path.as_ref())?;
     Ok((
-        file_list_to_json(file_list.into_iter().collect()),
+        file_list_to_json(path.as_ref(), file_list.into_iter().collect()),
         algorithm,
     ))
 }
 
-fn file_list_to_json(file_list: Vec<(PathBuf, String)>) -> Value {
+fn file_list_to_json(root_dir: &Path, file_list: Vec<(PathBuf, String)>) -> Value {
     Value::Array(
         file_list
             .into_iter()
             .map(|(path, name)| {
-                Value::Array(vec![
+                // the size is shown in the listings of the source browser
+                let size = fs::metadata(root_dir.join(&path)).map(|metadata| metadata.len());
+                let mut entry = vec![
                     Value::String(name),
                     Value::String(path.into_os_string().into_string().unwrap()),
-                ])
+                ];
+                if let Ok(size) = size {
+                    entry.push(size.into());
+                }
+                Value::Array(entry)
             })
             .collect(),
     )
@@ -754,6 +754,16 @@ pub fn migrate(version: Option<Version>, conn: &mut Client) -> crate::error::Res
             "ALTER TABLE releases ADD COLUMN archive_storage BOOL NOT NULL DEFAULT FALSE;",
             "ALTER TABLE releases DROP COLUMN archive_storage;",
         ),
+        migration!(
+            context, 31, "record deleted releases",
+            "CREATE TABLE deleted_releases (
+                name VARCHAR(255) NOT NULL,
+                version VARCHAR(100) NOT NULL,
+                deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                PRIMARY KEY (name, version)
+            );",
+            "DROP TABLE deleted_releases;",
+        ),
     ];
 
     for migration in migrations {
@@ -70,6 +70,10 @@ metrics! {
 
         /// Number of files uploaded to the storage backend
         pub(crate) uploaded_files_total: IntCounter,
+        /// Number of source files served from the in-process cache
+        pub(crate) source_file_cache_hits: IntCounter,
+        /// Number of source files that had to be fetched from the storage backend
+        pub(crate) source_file_cache_misses: IntCounter,
 
         /// The number of attempted files that failed due to a memory limit
         pub(crate) html_rewrite_ooms: IntCounter,
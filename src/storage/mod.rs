@@ -2,10 +2,12 @@ mod archive_index;
 mod compression;
 mod database;
 mod s3;
+mod source_cache;
 
 pub use self::compression::{compress, decompress, CompressionAlgorithm, CompressionAlgorithms};
 use self::database::DatabaseBackend;
 use self::s3::S3Backend;
+use self::source_cache::{source_file_cache, SourceFileCache, SourceFileKey};
 use crate::error::Result;
 use crate::{db::Pool, Config, Metrics};
 use anyhow::{anyhow, ensure};
@@ -109,12 +111,19 @@ enum StorageBackend {
 pub struct Storage {
     backend: StorageBackend,
     config: Arc<Config>,
+    metrics: Arc<Metrics>,
+    source_file_cache: SourceFileCache,
 }
 
 impl Storage {
     pub fn new(pool: Pool, metrics: Arc<Metrics>, config: Arc<Config>) -> Result<Self> {
         Ok(Storage {
             config: config.clone(),
+            metrics: metrics.clone(),
+            source_file_cache: source_file_cache(
+                config.source_file_cache_capacity,
+                config.source_file_cache_size,
+            ),
             backend: match config.storage_backend {
                 StorageKind::Database => {
                     StorageBackend::Database(DatabaseBackend::new(pool, metrics))
@@ -159,12 +168,37 @@ impl Storage {
         })
     }
 
+    /// Fetches a source file of a release, from the in-process cache if it was fetched recently,
+    /// see `Config::source_file_cache_capacity`
     pub(crate) fn fetch_source_file(
         &self,
         name: &str,
         version: &str,
         path: &str,
         archive_storage: bool,
+    ) -> Result<Blob> {
+        if !self.source_file_cache.is_enabled() {
+            return self.fetch_uncached_source_file(name, version, path, archive_storage);
+        }
+
+        let key = SourceFileKey::new(name, version, path, archive_storage);
+        if let Some(blob) = self.source_file_cache.get(&key) {
+            self.metrics.source_file_cache_hits.inc();
+            return Ok(blob);
+        }
+        self.metrics.source_file_cache_misses.inc();
+
+        let blob = self.fetch_uncached_source_file(name, version, path, archive_storage)?;
+        self.source_file_cache.insert(key, blob.clone());
+        Ok(blob)
+    }
+
+    fn fetch_uncached_source_file(
+        &self,
+        name: &str,
+        version: &str,
+        path: &str,
+        archive_storage: bool,
     ) -> Result<Blob> {
         Ok(if archive_storage {
             self.get_from_archive(
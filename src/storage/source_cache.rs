@@ -0,0 +1,76 @@
+//! In-process cache of the source files fetched for the source browser
+
+use crate::{storage::Blob, utils::LruCache};
+
+/// Identifies a source file of a release, the way `Storage::fetch_source_file` looks it up
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct SourceFileKey {
+    name: String,
+    version: String,
+    path: String,
+    archive_storage: bool,
+}
+
+impl SourceFileKey {
+    pub(super) fn new(name: &str, version: &str, path: &str, archive_storage: bool) -> Self {
+        SourceFileKey {
+            name: name.into(),
+            version: version.into(),
+            path: path.into(),
+            archive_storage,
+        }
+    }
+}
+
+/// A least recently used cache of source files, bounded both by the number of files and by their
+/// combined size. The source of a release never changes, so entries never go stale.
+pub(super) type SourceFileCache = LruCache<SourceFileKey, Blob>;
+
+/// Creates a cache of at most `capacity` files taking up at most `max_size` bytes. A capacity of
+/// zero disables the cache.
+pub(super) fn source_file_cache(capacity: usize, max_size: usize) -> SourceFileCache {
+    LruCache::with_max_size(capacity, max_size, |blob| blob.content.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn blob(path: &str, size: usize) -> Blob {
+        Blob {
+            path: path.into(),
+            mime: "text/rust".into(),
+            date_updated: Utc::now(),
+            content: vec![b'a'; size],
+            compression: None,
+        }
+    }
+
+    fn key(path: &str) -> SourceFileKey {
+        SourceFileKey::new("fake", "0.1.0", path, false)
+    }
+
+    #[test]
+    fn size_is_bounded() {
+        let cache = source_file_cache(10, 100);
+        cache.insert(key("a.rs"), blob("a.rs", 60));
+        cache.insert(key("b.rs"), blob("b.rs", 60));
+        assert!(cache.get(&key("a.rs")).is_none());
+        assert!(cache.get(&key("b.rs")).is_some());
+
+        // a file larger than the whole cache isn't cached at all
+        cache.insert(key("c.rs"), blob("c.rs", 101));
+        assert!(cache.get(&key("c.rs")).is_none());
+        assert!(cache.get(&key("b.rs")).is_some());
+    }
+
+    #[test]
+    fn archive_storage_is_part_of_the_key() {
+        let cache = source_file_cache(10, 100);
+        cache.insert(key("a.rs"), blob("a.rs", 1));
+        assert!(cache
+            .get(&SourceFileKey::new("fake", "0.1.0", "a.rs", true))
+            .is_none());
+    }
+}
@@ -169,6 +169,10 @@ impl TestEnvironment {
         config.local_archive_cache_path =
             std::env::temp_dir().join(format!("docsrs-test-index-{}", rand::random::<u64>()));
 
+        // Tests replace files in storage, which a cache would hide.
+        config.source_file_cache_capacity = 0;
+        config.source_release_cache_capacity = 0;
+
         config
     }
 
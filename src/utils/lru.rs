@@ -0,0 +1,149 @@
+//! A least recently used cache for values that can be shared between requests
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::Mutex;
+
+struct Entries<K, V> {
+    values: HashMap<K, (u64, V)>,
+    /// The keys of `values` by when they were last used, the least recently used first
+    by_use: BTreeMap<u64, K>,
+    /// The combined size of `values`, as measured by `LruCache::size_of`
+    size: usize,
+    /// Counts the uses of the cache, it's what `by_use` is ordered by
+    clock: u64,
+}
+
+/// A least recently used cache, bounded by the number of values and optionally by their combined
+/// size. Values are cloned out of the cache, so they should be cheap to clone.
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    max_size: usize,
+    size_of: fn(&V) -> usize,
+    entries: Mutex<Entries<K, V>>,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> LruCache<K, V> {
+    /// Creates a cache of at most `capacity` values. A capacity of zero disables the cache.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self::with_max_size(capacity, usize::MAX, |_| 0)
+    }
+
+    /// Creates a cache of at most `capacity` values whose sizes, as returned by `size_of`, add up
+    /// to at most `max_size`. A capacity of zero disables the cache.
+    pub(crate) fn with_max_size(
+        capacity: usize,
+        max_size: usize,
+        size_of: fn(&V) -> usize,
+    ) -> Self {
+        LruCache {
+            capacity,
+            max_size,
+            size_of,
+            entries: Mutex::new(Entries {
+                values: HashMap::new(),
+                by_use: BTreeMap::new(),
+                size: 0,
+                clock: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let now = entries.clock;
+
+        let (last_used, value) = entries.values.get_mut(key)?;
+        let previous_use = std::mem::replace(last_used, now);
+        let value = value.clone();
+        entries.by_use.remove(&previous_use);
+        entries.by_use.insert(now, key.clone());
+        Some(value)
+    }
+
+    /// Adds a value to the cache, evicting the least recently used values until it fits. Values
+    /// larger than the whole cache aren't added.
+    pub(crate) fn insert(&self, key: K, value: V) {
+        let size = (self.size_of)(&value);
+        if !self.is_enabled() || size > self.max_size {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let now = entries.clock;
+
+        if let Some((last_used, previous)) = entries.values.remove(&key) {
+            entries.by_use.remove(&last_used);
+            entries.size -= (self.size_of)(&previous);
+        }
+        while entries.values.len() >= self.capacity || entries.size + size > self.max_size {
+            let oldest = match entries.by_use.keys().next() {
+                Some(&oldest) => oldest,
+                None => break,
+            };
+            let evicted = entries.by_use.remove(&oldest).unwrap();
+            if let Some((_, evicted)) = entries.values.remove(&evicted) {
+                entries.size -= (self.size_of)(&evicted);
+            }
+        }
+
+        entries.size += size;
+        entries.by_use.insert(now, key.clone());
+        entries.values.insert(key, (now, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_values_are_evicted() {
+        let cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        cache.insert("c", 3);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some(3));
+    }
+
+    #[test]
+    fn size_is_bounded() {
+        let cache = LruCache::with_max_size(10, 100, |value: &Vec<u8>| value.len());
+        cache.insert("a", vec![0; 60]);
+        cache.insert("b", vec![0; 60]);
+        assert!(cache.get(&"a").is_none());
+        assert!(cache.get(&"b").is_some());
+
+        // a value larger than the whole cache isn't cached at all
+        cache.insert("c", vec![0; 101]);
+        assert!(cache.get(&"c").is_none());
+        assert!(cache.get(&"b").is_some());
+    }
+
+    #[test]
+    fn replaced_values_are_resized() {
+        let cache = LruCache::with_max_size(10, 100, |value: &Vec<u8>| value.len());
+        cache.insert("a", vec![0; 60]);
+        cache.insert("a", vec![0; 30]);
+        cache.insert("b", vec![0; 60]);
+        assert_eq!(cache.get(&"a").map(|value| value.len()), Some(30));
+        assert!(cache.get(&"b").is_some());
+    }
+
+    #[test]
+    fn disabled() {
+        let cache = LruCache::new(0);
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), None);
+    }
+}
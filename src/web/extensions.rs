@@ -1,4 +1,4 @@
-use crate::web::page::TemplateData;
+use crate::web::{page::TemplateData, source::SourceCaches};
 use crate::{
     db::Pool, repositories::RepositoryStatsUpdater, BuildQueue, Config, Context, Metrics, Storage,
 };
@@ -15,6 +15,7 @@ pub(super) struct InjectExtensions {
     metrics: Arc<Metrics>,
     template_data: Arc<TemplateData>,
     repository_stats_updater: Arc<RepositoryStatsUpdater>,
+    source_caches: Arc<SourceCaches>,
 }
 
 impl InjectExtensions {
@@ -22,10 +23,12 @@ impl InjectExtensions {
         context: &dyn Context,
         template_data: Arc<TemplateData>,
     ) -> Result<Self, Error> {
+        let config = context.config()?;
         Ok(Self {
             build_queue: context.build_queue()?,
             pool: context.pool()?,
-            config: context.config()?,
+            source_caches: Arc::new(SourceCaches::new(&config)),
+            config,
             storage: context.storage()?,
             metrics: context.metrics()?,
             repository_stats_updater: context.repository_stats_updater()?,
@@ -46,6 +49,8 @@ impl BeforeMiddleware for InjectExtensions {
             .insert::<TemplateData>(self.template_data.clone());
         req.extensions
             .insert::<RepositoryStatsUpdater>(self.repository_stats_updater.clone());
+        req.extensions
+            .insert::<SourceCaches>(self.source_caches.clone());
 
         Ok(())
     }
@@ -66,3 +71,4 @@ key!(Storage => Arc<Storage>);
 key!(Metrics => Arc<Metrics>);
 key!(TemplateData => Arc<TemplateData>);
 key!(RepositoryStatsUpdater => Arc<RepositoryStatsUpdater>);
+key!(SourceCaches => Arc<SourceCaches>);
@@ -0,0 +1,510 @@
+//! Syntax highlighting of source files on the server
+//!
+//! This is a small lexer for the languages most files of crates are written in, it only tells
+//! comments, strings, keywords, constants and the names of definitions apart from the rest of the
+//! code. Anything it doesn't know is left to highlight.js in the browser.
+
+use std::time::{Duration, Instant};
+
+/// Maximum size of a file highlighted on the server, to keep render times bounded. Larger files
+/// are left to the browser.
+pub(crate) const MAX_HIGHLIGHT_SIZE: usize = 512 * 1024;
+
+/// How long highlighting a single file may take on the server. Some files below
+/// `MAX_HIGHLIGHT_SIZE` are still slow to highlight, they're shown as plain text once this runs
+/// out instead of holding up the worker.
+pub(crate) const HIGHLIGHT_BUDGET: Duration = Duration::from_millis(500);
+
+/// The kinds of highlighted tokens, each is wrapped in a span with a `syntax-` class
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Comment,
+    String,
+    Keyword,
+    Constant,
+    /// The name of something being defined, like the function of `fn foo` or a TOML table
+    Entity,
+}
+
+impl Token {
+    fn class(self) -> &'static str {
+        match self {
+            Token::Comment => "syntax-comment",
+            Token::String => "syntax-string",
+            Token::Keyword => "syntax-keyword",
+            Token::Constant => "syntax-constant",
+            Token::Entity => "syntax-entity",
+        }
+    }
+}
+
+/// How the tokens of a language are recognized
+struct Language {
+    /// The names of the language and the file extensions it's found by, the first one is used in
+    /// the class of the highlighted code, e.g. `syntax-rust`
+    names: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    /// The start and end of block comments, which can be nested
+    block_comment: Option<(&'static str, &'static str)>,
+    /// The delimiters of strings, and whether a backslash escapes the next character in them.
+    /// They're tried in order, so longer delimiters go first.
+    quotes: &'static [(&'static str, bool)],
+    keywords: &'static [&'static str],
+    constants: &'static [&'static str],
+    /// Keywords followed by the name of what they define, which is highlighted as an entity
+    definitions: &'static [&'static str],
+    numbers: bool,
+    /// Rust's `'a'` character literals, which are told apart from `'a` lifetimes, and its
+    /// `r#"..."#` raw strings
+    rust_literals: bool,
+    /// Whether a `[table]` starting a line is a heading, like in TOML
+    tables: bool,
+    /// Whether a line starting with `#` is a heading, like in Markdown
+    headings: bool,
+}
+
+const RUST: Language = Language {
+    names: &["rust", "rs"],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &[("\"", true)],
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "static", "struct", "super", "trait", "type", "union", "unsafe",
+        "use", "where", "while",
+    ],
+    constants: &["true", "false", "self", "Self", "None", "Some", "Ok", "Err"],
+    definitions: &["fn", "struct", "enum", "trait", "type", "mod", "union"],
+    numbers: true,
+    rust_literals: true,
+    tables: false,
+    headings: false,
+};
+
+const TOML: Language = Language {
+    names: &["toml"],
+    line_comment: Some("#"),
+    block_comment: None,
+    quotes: &[("\"\"\"", true), ("'''", false), ("\"", true), ("'", false)],
+    keywords: &[],
+    constants: &["true", "false"],
+    definitions: &[],
+    numbers: true,
+    rust_literals: false,
+    tables: true,
+    headings: false,
+};
+
+const JSON: Language = Language {
+    names: &["json"],
+    line_comment: None,
+    block_comment: None,
+    quotes: &[("\"", true)],
+    keywords: &[],
+    constants: &["true", "false", "null"],
+    definitions: &[],
+    numbers: true,
+    rust_literals: false,
+    tables: false,
+    headings: false,
+};
+
+const MARKDOWN: Language = Language {
+    names: &["markdown", "md"],
+    line_comment: None,
+    block_comment: None,
+    // code blocks and inline code
+    quotes: &[("```", false), ("`", false)],
+    keywords: &[],
+    constants: &[],
+    definitions: &[],
+    numbers: false,
+    rust_literals: false,
+    tables: false,
+    headings: true,
+};
+
+const LANGUAGES: &[&Language] = &[&RUST, &TOML, &JSON, &MARKDOWN];
+
+fn find_language(name: &str) -> Option<&'static Language> {
+    LANGUAGES
+        .iter()
+        .copied()
+        .find(|language| language.names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+}
+
+/// Code highlighted on the server, see `highlight`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Highlighted {
+    Html(String),
+    /// Highlighting the code took longer than `HIGHLIGHT_BUDGET` and was given up
+    TimedOut,
+}
+
+impl Highlighted {
+    pub(crate) fn into_html(self) -> Option<String> {
+        match self {
+            Highlighted::Html(html) => Some(html),
+            Highlighted::TimedOut => None,
+        }
+    }
+}
+
+/// Highlights `code` as `language`, which is either the name of a language as highlight.js knows
+/// it or a file extension.
+///
+/// Returns the code as HTML, or `None` if the language isn't known or the code is too large.
+/// Highlighting is given up once it takes longer than `HIGHLIGHT_BUDGET`.
+pub(crate) fn highlight(code: &str, language: &str) -> Option<Highlighted> {
+    highlight_until(code, language, Instant::now() + HIGHLIGHT_BUDGET)
+}
+
+/// Highlights `code` like `highlight` does, giving up at `deadline`. The deadline is checked
+/// between lines, a single line is always highlighted completely.
+fn highlight_until(code: &str, language: &str, deadline: Instant) -> Option<Highlighted> {
+    if code.len() > MAX_HIGHLIGHT_SIZE {
+        return None;
+    }
+    let language = find_language(language)?;
+
+    let mut html = format!(
+        r#"<span class="syntax-source syntax-{}">"#,
+        language.names[0]
+    );
+    let mut lexer = Lexer {
+        language,
+        code,
+        pos: 0,
+        line_start: true,
+        define_next: false,
+        table_name: None,
+    };
+    while lexer.pos < code.len() {
+        if lexer.line_start && Instant::now() >= deadline {
+            return Some(Highlighted::TimedOut);
+        }
+        let (token, text) = lexer.next_token();
+        match token {
+            Some(token) => {
+                html.push_str(r#"<span class=""#);
+                html.push_str(token.class());
+                html.push_str(r#"">"#);
+                html.push_str(&tera::escape_html(text));
+                html.push_str("</span>");
+            }
+            None => html.push_str(&tera::escape_html(text)),
+        }
+    }
+    html.push_str("</span>");
+    Some(Highlighted::Html(html))
+}
+
+struct Lexer<'a> {
+    language: &'static Language,
+    code: &'a str,
+    pos: usize,
+    /// Whether only whitespace precedes `pos` on its line
+    line_start: bool,
+    /// Whether the next identifier is the name of a definition, see `Language::definitions`
+    define_next: bool,
+    /// The length of the name of the table whose opening brackets were just scanned, see
+    /// `Language::tables`
+    table_name: Option<usize>,
+}
+
+impl<'a> Lexer<'a> {
+    /// Returns the next token and its text, tokens that aren't highlighted have no kind
+    fn next_token(&mut self) -> (Option<Token>, &'a str) {
+        let (code, start) = (self.code, self.pos);
+        let (token, len) = self.scan(&code[start..]);
+        let text = &code[start..start + len];
+        self.pos += len;
+
+        if text.ends_with('\n') {
+            self.line_start = true;
+        } else if !text.trim().is_empty() {
+            self.line_start = false;
+        }
+        if token != Some(Token::Keyword) && !text.trim().is_empty() {
+            self.define_next = false;
+        }
+        (token, text)
+    }
+
+    /// Finds the length of the token at the start of `rest`, which is never empty
+    fn scan(&mut self, rest: &str) -> (Option<Token>, usize) {
+        let language = self.language;
+        let first = rest.chars().next().unwrap();
+
+        if let Some(len) = self.table_name.take() {
+            return (Some(Token::Entity), len);
+        }
+        if first.is_whitespace() {
+            // whitespace is split after newlines, so the deadline is checked for each line
+            let len = rest
+                .find(|c: char| !c.is_whitespace() || c == '\n')
+                .unwrap_or(rest.len());
+            return (
+                None,
+                if rest[len..].starts_with('\n') {
+                    len + 1
+                } else {
+                    len
+                },
+            );
+        }
+
+        if self.line_start {
+            if language.headings && first == '#' {
+                return (Some(Token::Entity), line_len(rest));
+            }
+            if language.tables && first == '[' {
+                let brackets = if rest.starts_with("[[") { 2 } else { 1 };
+                if let Some(len) = rest[brackets..line_len(rest)]
+                    .find(']')
+                    .filter(|&len| len > 0)
+                {
+                    // the brackets themselves aren't highlighted, only the table's name
+                    self.table_name = Some(len);
+                    return (None, brackets);
+                }
+            }
+        }
+        if let Some(marker) = language.line_comment {
+            if rest.starts_with(marker) {
+                return (Some(Token::Comment), line_len(rest));
+            }
+        }
+        if let Some((open, close)) = language.block_comment {
+            if rest.starts_with(open) {
+                return (Some(Token::Comment), block_comment_len(rest, open, close));
+            }
+        }
+
+        if language.rust_literals {
+            if let Some(len) = raw_string_len(rest) {
+                return (Some(Token::String), len);
+            }
+            if first == '\'' {
+                return match char_literal_len(rest) {
+                    Some(len) => (Some(Token::String), len),
+                    // a lifetime, its name follows as an identifier
+                    None => (None, 1),
+                };
+            }
+        }
+        for &(quote, escapes) in language.quotes {
+            if rest.starts_with(quote) {
+                return (Some(Token::String), string_len(rest, quote, escapes));
+            }
+        }
+
+        if language.numbers && first.is_ascii_digit() {
+            return (Some(Token::Constant), number_len(rest));
+        }
+
+        if first.is_alphabetic() || first == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            let token = if self.define_next {
+                Some(Token::Entity)
+            } else if language.keywords.contains(&word) {
+                self.define_next = language.definitions.contains(&word);
+                Some(Token::Keyword)
+            } else if language.constants.contains(&word) {
+                Some(Token::Constant)
+            } else {
+                None
+            };
+            return (token, len);
+        }
+
+        (None, first.len_utf8())
+    }
+}
+
+/// The length of the rest of the line, without its newline
+fn line_len(rest: &str) -> usize {
+    rest.find('\n').unwrap_or(rest.len())
+}
+
+/// The length of a block comment, including any nested in it. An unclosed comment runs to the end
+/// of the code.
+fn block_comment_len(rest: &str, open: &str, close: &str) -> usize {
+    let mut depth = 0;
+    let mut pos = 0;
+    while pos < rest.len() {
+        if rest[pos..].starts_with(open) {
+            depth += 1;
+            pos += open.len();
+        } else if rest[pos..].starts_with(close) {
+            depth -= 1;
+            pos += close.len();
+            if depth == 0 {
+                return pos;
+            }
+        } else {
+            pos += rest[pos..].chars().next().unwrap().len_utf8();
+        }
+    }
+    rest.len()
+}
+
+/// The length of a string delimited by `quote`. An unclosed string runs to the end of the code.
+fn string_len(rest: &str, quote: &str, escapes: bool) -> usize {
+    let mut chars = rest[quote.len()..].char_indices();
+    while let Some((idx, c)) = chars.next() {
+        if escapes && c == '\\' {
+            chars.next();
+        } else if rest[quote.len() + idx..].starts_with(quote) {
+            return quote.len() + idx + quote.len();
+        }
+    }
+    rest.len()
+}
+
+/// The length of a Rust raw string like `r#"..."#` or `br"..."`, if `rest` starts with one
+fn raw_string_len(rest: &str) -> Option<usize> {
+    let after_prefix = rest.strip_prefix("br").or_else(|| rest.strip_prefix('r'))?;
+    let hashes = after_prefix.len() - after_prefix.trim_start_matches('#').len();
+    if !after_prefix[hashes..].starts_with('"') {
+        return None;
+    }
+
+    let prefix_len = rest.len() - after_prefix.len() + hashes + 1;
+    let close = format!("\"{}", "#".repeat(hashes));
+    Some(
+        rest[prefix_len..]
+            .find(&close)
+            .map_or(rest.len(), |end| prefix_len + end + close.len()),
+    )
+}
+
+/// The length of a Rust character literal like `'a'` or `'\n'`, or `None` for a lifetime
+fn char_literal_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    let (_, c) = chars.next()?;
+    if c == '\\' {
+        // escapes like `\u{1F980}` are the longest, they're closed on the same line
+        let (idx, escaped) = chars.next()?;
+        let start = idx + escaped.len_utf8();
+        let end = rest.get(start..line_len(rest))?.find('\'')?;
+        return Some(start + end + 1);
+    }
+    match chars.next() {
+        Some((idx, '\'')) if c != '\'' => Some(idx + 1),
+        _ => None,
+    }
+}
+
+/// The length of a number, including suffixes like in `1u8` and exponents like in `1e10`
+fn number_len(rest: &str) -> usize {
+    let mut len = 0;
+    let bytes = rest.as_bytes();
+    while len < bytes.len() {
+        let b = bytes[len];
+        // a dot only continues the number if a digit follows, it isn't part of `1..2` or `1.max()`
+        let fraction = b == b'.' && bytes.get(len + 1).map_or(false, u8::is_ascii_digit);
+        if b.is_ascii_alphanumeric() || b == b'_' || fraction {
+            len += 1;
+        } else {
+            break;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{highlight, highlight_until, Highlighted, MAX_HIGHLIGHT_SIZE};
+    use std::time::{Duration, Instant};
+
+    fn highlight_html(code: &str, language: &str) -> String {
+        match highlight(code, language) {
+            Some(Highlighted::Html(html)) => html,
+            other => panic!("{:?} wasn't highlighted: {:?}", code, other),
+        }
+    }
+
+    #[test]
+    fn rust() {
+        let html = highlight_html("fn foo() {}\n", "rust");
+        assert!(html.contains(r#"<span class="syntax-source syntax-rust">"#));
+        assert!(html.contains(r#"<span class="syntax-keyword">fn</span>"#));
+        assert!(html.contains(r#"<span class="syntax-entity">foo</span>"#));
+        assert_eq!(html, highlight_html("fn foo() {}\n", "rs"));
+    }
+
+    #[test]
+    fn rust_literals() {
+        let html = highlight_html(
+            "// a comment\nlet s: &'static str = r#\"a \"quoted\" word\"#; /* a /* nested */ comment */\nlet c = '\\'';\nlet n = 1.5e3f64;\n",
+            "rust",
+        );
+        assert!(html.contains(r#"<span class="syntax-comment">&#x2F;&#x2F; a comment</span>"#));
+        // the lifetime isn't taken for the start of a character
+        assert!(html.contains(r#"&amp;&#x27;<span class="syntax-keyword">static</span>"#));
+        assert!(html.contains(
+            r#"<span class="syntax-string">r#&quot;a &quot;quoted&quot; word&quot;#</span>;"#
+        ));
+        assert!(html.contains(
+            r#"<span class="syntax-comment">&#x2F;* a &#x2F;* nested *&#x2F; comment *&#x2F;</span>"#
+        ));
+        assert!(html.contains(r#"<span class="syntax-string">&#x27;\&#x27;&#x27;</span>"#));
+        assert!(html.contains(r#"<span class="syntax-constant">1.5e3f64</span>"#));
+    }
+
+    #[test]
+    fn toml() {
+        let html = highlight_html("[package]\nname = \"foo\" # comment\n", "toml");
+        assert!(html.contains("syntax-source syntax-toml"));
+        assert!(html.contains(r#"[<span class="syntax-entity">package</span>]"#));
+        assert!(html.contains(r#"<span class="syntax-string">&quot;foo&quot;</span>"#));
+        assert!(html.contains(r#"<span class="syntax-comment"># comment</span>"#));
+    }
+
+    #[test]
+    fn json_and_markdown() {
+        let html = highlight_html("{\"a\": [1, true, null]}", "json");
+        assert!(html.contains(r#"<span class="syntax-string">&quot;a&quot;</span>"#));
+        assert!(html.contains(r#"<span class="syntax-constant">null</span>"#));
+
+        let html = highlight_html("# Title\n\nSome `code`, 1 word\n", "md");
+        assert!(html.contains(r#"<span class="syntax-entity"># Title</span>"#));
+        assert!(html.contains(r#"<span class="syntax-string">`code`</span>, 1 word"#));
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        let html = highlight_html("<script>alert(1)</script>\n", "markdown");
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;"));
+    }
+
+    #[test]
+    fn unknown_language() {
+        assert_eq!(highlight("foo", "not-a-language"), None);
+    }
+
+    #[test]
+    fn too_large() {
+        let code = "// ".to_owned() + &"a".repeat(MAX_HIGHLIGHT_SIZE);
+        assert_eq!(highlight(&code, "rust"), None);
+    }
+
+    #[test]
+    fn budget() {
+        let code = "fn foo() {}\n".repeat(100);
+        assert_eq!(
+            highlight_until(&code, "rust", Instant::now()),
+            Some(Highlighted::TimedOut)
+        );
+        assert!(matches!(
+            highlight_until(&code, "rust", Instant::now() + Duration::from_secs(60)),
+            Some(Highlighted::Html(_))
+        ));
+    }
+}
@@ -85,6 +85,7 @@ mod error;
 mod extensions;
 mod features;
 mod file;
+pub(crate) mod highlight;
 pub(crate) mod metrics;
 mod releases;
 mod routes;
@@ -105,7 +105,43 @@ pub(super) fn build_routes() -> Routes {
         "/crate/:name/:version/features",
         super::features::build_features_handler,
     );
+    routes.static_resource(
+        "/crate/:name/:version/source-files.json",
+        super::source::source_files_handler,
+    );
+    // Takes precedence over a `checksums.json` at the root of a crate's source in the source
+    // browser below.
+    routes.static_resource(
+        "/crate/:name/:version/source/checksums.json",
+        super::source::source_checksums_handler,
+    );
     routes.internal_page(
+        "/source-by-hash/:name/:hash",
+        super::source::source_by_hash_handler,
+    );
+    routes.static_resource(
+        "/crate/:name/:version/source-tree",
+        super::source::source_tree_handler,
+    );
+    routes.static_resource(
+        "/crate/:name/:version/source-warm",
+        super::source::source_warm_handler,
+    );
+    routes.static_resource(
+        "/crate/:name/:version/source.tar.gz",
+        super::source::source_tarball_handler,
+    );
+    routes.static_resource(
+        "/crate/:name/source/*",
+        super::source::file_versions_handler,
+    );
+    routes.static_resource(
+        "/crate/:name/source-diff",
+        super::source::source_diff_handler,
+    );
+    // Registered as a static resource so that no automatic `source/` -> `source` redirect is added,
+    // which would conflict with the source browser's root.
+    routes.static_resource(
         "/crate/:name/:version/source",
         SimpleRedirect::new(|url| url.set_path(&format!("{}/", url.path()))),
     );
@@ -117,6 +153,25 @@ pub(super) fn build_routes() -> Routes {
         "/crate/:name/:version/source/*",
         super::source::source_browser_handler,
     );
+    // Short aliases of the source browser's URLs, e.g. for embedding. They live below `/-/`
+    // instead of a new top level prefix, which would hijack the documentation of a crate.
+    for pattern in &[
+        "/-/s/:name/:version",
+        "/-/s/:name/:version/",
+        "/-/s/:name/:version/*",
+    ] {
+        routes.static_resource(
+            pattern,
+            SimpleRedirect::new(|url| {
+                let path = url.path().trim_start_matches("/-/s/").to_owned();
+                let mut segments = path.splitn(3, '/');
+                let name = segments.next().unwrap_or_default();
+                let version = segments.next().unwrap_or_default();
+                let rest = segments.next().unwrap_or_default();
+                url.set_path(&format!("/crate/{}/{}/source/{}", name, version, rest));
+            }),
+        );
+    }
     routes.internal_page(
         "/crate/:name/:version/target-redirect/*",
         super::rustdoc::target_redirect_handler,
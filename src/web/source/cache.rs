@@ -0,0 +1,299 @@
+//! In-process caches of what the source browser derives from the source of whole releases
+
+use super::{
+    classify_path,
+    content::{public_module_paths, CRATE_ROOTS},
+    listing::{
+        directory_readme_name, language_stats, tree_shape, FileList, ListingOrder, TreeShape,
+    },
+    resolve_source_release, set_nosniff, PathKind, SourceRelease,
+};
+use crate::{utils::LruCache, web::error::Nope, Config, Storage};
+use iron::{headers::ContentType, status, IronResult, Request, Response};
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+use std::convert::Infallible;
+
+/// A least recently used cache of values computed from the source of a release, keyed by the
+/// release's name and version. The source of a release never changes, so entries never go stale.
+pub(super) struct ReleaseCache<T>(LruCache<(String, String), T>);
+
+impl<T: Clone> ReleaseCache<T> {
+    /// Creates a cache of the values of at most `capacity` releases. A capacity of zero disables
+    /// the cache.
+    pub(super) fn new(capacity: usize) -> Self {
+        ReleaseCache(LruCache::new(capacity))
+    }
+
+    /// Returns the cached value for a release, computing and caching it with `compute` if it
+    /// isn't cached yet. Errors aren't cached. The lock isn't held while computing, so concurrent
+    /// requests for a release that isn't cached yet may compute its value more than once.
+    pub(super) fn get_or_try_insert_with<E>(
+        &self,
+        name: &str,
+        version: &str,
+        compute: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        if !self.0.is_enabled() {
+            return compute();
+        }
+
+        let key = (name.to_owned(), version.to_owned());
+        if let Some(value) = self.0.get(&key) {
+            return Ok(value);
+        }
+        let value = compute()?;
+        self.0.insert(key, value.clone());
+        Ok(value)
+    }
+
+    /// Like `get_or_try_insert_with`, for values whose computation can't fail
+    pub(super) fn get_or_insert_with(
+        &self,
+        name: &str,
+        version: &str,
+        compute: impl FnOnce() -> T,
+    ) -> T {
+        self.get_or_try_insert_with::<Infallible>(name, version, || Ok(compute()))
+            .unwrap_or_else(|never| match never {})
+    }
+}
+
+/// The caches shared by all requests to the source browser, see
+/// `Config::source_release_cache_capacity`
+pub struct SourceCaches {
+    /// The checksums served by `source_checksums_handler`, `None` for releases too large to hash
+    pub(super) checksums: ReleaseCache<Option<BTreeMap<String, String>>>,
+    /// The files tagged as public in listings of `src/`, see `public_module_paths`
+    pub(super) public_module_paths: ReleaseCache<HashSet<String>>,
+    /// The files per language shown at the crate root, see `language_stats`
+    pub(super) language_stats: ReleaseCache<Vec<(String, usize)>>,
+    /// The shape of the directory tree shown at the crate root, see `tree_shape`
+    pub(super) tree_shape: ReleaseCache<Option<TreeShape>>,
+}
+
+impl SourceCaches {
+    pub(crate) fn new(config: &Config) -> Self {
+        SourceCaches {
+            checksums: ReleaseCache::new(config.source_release_cache_capacity),
+            public_module_paths: ReleaseCache::new(config.source_release_cache_capacity),
+            language_stats: ReleaseCache::new(config.source_release_cache_capacity),
+            tree_shape: ReleaseCache::new(config.source_release_cache_capacity),
+        }
+    }
+}
+
+/// Fills the caches with what the root page of a release shows and with its crate roots, so the
+/// first visitors of a popular release don't wait for the storage. Only operators holding
+/// `Config::source_warm_token` can warm releases, since warming many of them evicts the ones
+/// visitors are viewing. Answers with the source files now in the cache of `Storage`.
+pub fn source_warm_handler(req: &mut Request) -> IronResult<Response> {
+    if !is_operator(req) {
+        return Err(Nope::ResourceNotFound.into());
+    }
+    set_nosniff(resolve_source_release(req, warm_release))
+}
+
+/// Whether the request carries `Config::source_warm_token` as its bearer token
+fn is_operator(req: &Request) -> bool {
+    let token = match req
+        .extensions
+        .get::<Config>()
+        .and_then(|config| config.source_warm_token.as_deref())
+    {
+        Some(token) => token,
+        None => return false,
+    };
+
+    req.headers
+        .get_raw("Authorization")
+        .and_then(|values| values.get(0))
+        .and_then(|value| std::str::from_utf8(value).ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        == Some(token)
+}
+
+fn warm_release(req: &Request, name: &str, release: SourceRelease) -> IronResult<Response> {
+    let caches = extension!(req, SourceCaches);
+    let storage = extension!(req, Storage);
+    let (version, archive_storage) = (&release.metadata.version, release.archive_storage);
+    let files = release.file_list();
+
+    // the same values `render_source_page` takes from the caches for the crate root
+    caches
+        .language_stats
+        .get_or_insert_with(name, version, || language_stats(files));
+    caches
+        .tree_shape
+        .get_or_insert_with(name, version, || tree_shape(files));
+    caches
+        .public_module_paths
+        .get_or_insert_with(name, version, || {
+            public_module_paths(storage, name, version, archive_storage)
+        });
+
+    // the README shown below the root listing and the files visitors usually open first
+    let root = FileList::from_path(
+        &release,
+        "",
+        false,
+        false,
+        ListingOrder::from_query(None, None),
+        None,
+    );
+    let readme = root
+        .as_ref()
+        .and_then(|root| directory_readme_name(&root.files));
+    let warmed: Vec<&str> = readme
+        .into_iter()
+        .chain(CRATE_ROOTS.iter().copied())
+        .filter(|path| classify_path(files, path) == PathKind::File)
+        .filter(|path| {
+            storage
+                .fetch_source_file(name, version, path, archive_storage)
+                .is_ok()
+        })
+        .collect();
+
+    let body = json!({ "files": warmed });
+    let mut response = Response::with((status::Ok, body.to_string()));
+    response.headers.set(ContentType::json());
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::*;
+    use serde_json::Value;
+
+    fn count(cache: &ReleaseCache<usize>, version: &str, computed: &mut usize) -> usize {
+        cache
+            .get_or_try_insert_with::<()>("fake", version, || {
+                *computed += 1;
+                Ok(*computed)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn values_are_computed_once() {
+        let cache = ReleaseCache::new(10);
+        let mut computed = 0;
+        assert_eq!(count(&cache, "0.1.0", &mut computed), 1);
+        assert_eq!(count(&cache, "0.1.0", &mut computed), 1);
+        assert_eq!(count(&cache, "0.2.0", &mut computed), 2);
+    }
+
+    #[test]
+    fn least_recently_used_releases_are_evicted() {
+        let cache = ReleaseCache::new(2);
+        let mut computed = 0;
+        count(&cache, "0.1.0", &mut computed);
+        count(&cache, "0.2.0", &mut computed);
+        count(&cache, "0.1.0", &mut computed);
+
+        count(&cache, "0.3.0", &mut computed);
+        assert_eq!(computed, 3);
+        assert_eq!(count(&cache, "0.1.0", &mut computed), 1);
+        assert_eq!(count(&cache, "0.2.0", &mut computed), 4);
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let cache = ReleaseCache::<usize>::new(10);
+        assert!(cache
+            .get_or_try_insert_with("fake", "0.1.0", || Err(()))
+            .is_err());
+        assert_eq!(
+            cache.get_or_try_insert_with::<()>("fake", "0.1.0", || Ok(1)),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn disabled() {
+        let cache = ReleaseCache::new(0);
+        let mut computed = 0;
+        count(&cache, "0.1.0", &mut computed);
+        assert_eq!(count(&cache, "0.1.0", &mut computed), 2);
+    }
+
+    #[test]
+    fn warming_fills_the_caches() {
+        wrapper(|env| {
+            env.override_config(|config| {
+                config.source_file_cache_capacity = 100;
+                config.source_release_cache_capacity = 10;
+                config.source_warm_token = Some("secret".into());
+            });
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("README.md", b"# fake")
+                .source_file("src/lib.rs", b"pub mod foo;")
+                .source_file("src/foo.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+            let metrics = env.metrics();
+
+            let response = web
+                .get("/crate/fake/0.1.0/source-warm")
+                .header("Authorization", "Bearer secret")
+                .send()?;
+            assert!(response.status().is_success());
+            let body: Value = response.json()?;
+            assert_eq!(body["files"], json!(["README.md", "src/lib.rs"]));
+
+            // neither the root listing nor the library root is fetched from the storage again
+            let (hits, misses) = (
+                metrics.source_file_cache_hits.get(),
+                metrics.source_file_cache_misses.get(),
+            );
+            assert!(web
+                .get("/crate/fake/0.1.0/source/")
+                .send()?
+                .text()?
+                .contains("fake"));
+            assert!(web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?
+                .contains("pub mod foo;"));
+            assert_eq!(metrics.source_file_cache_misses.get(), misses);
+            assert!(metrics.source_file_cache_hits.get() >= hits + 2);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn warming_is_only_for_operators() {
+        wrapper(|env| {
+            env.override_config(|config| config.source_warm_token = Some("secret".into()));
+            env.fake_release().name("fake").version("0.1.0").create()?;
+            let web = env.frontend();
+
+            assert_not_found("/crate/fake/0.1.0/source-warm", web)?;
+            let response = web
+                .get("/crate/fake/0.1.0/source-warm")
+                .header("Authorization", "Bearer wrong")
+                .send()?;
+            assert_eq!(response.status(), 404);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn warming_is_disabled_without_a_token() {
+        wrapper(|env| {
+            env.fake_release().name("fake").version("0.1.0").create()?;
+            let response = env
+                .frontend()
+                .get("/crate/fake/0.1.0/source-warm")
+                .header("Authorization", "Bearer ")
+                .send()?;
+            assert_eq!(response.status(), 404);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,811 @@
+//! Detecting and preparing the content of source files for display
+
+use super::encode_path_segment;
+use crate::{storage::Blob, web::MetaData, Storage};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+
+/// Maps file extensions to the language the file is written in
+const LANGUAGES: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("toml", "toml"),
+    ("md", "markdown"),
+    ("markdown", "markdown"),
+    ("json", "json"),
+    ("yml", "yaml"),
+    ("yaml", "yaml"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("hpp", "cpp"),
+    ("js", "javascript"),
+    ("ts", "typescript"),
+    ("html", "html"),
+    ("css", "css"),
+    ("py", "python"),
+    ("sh", "bash"),
+    ("ini", "ini"),
+    ("xml", "xml"),
+];
+
+/// The languages a file can be shown as with `?lang=`. Only the grammars of these are loaded
+/// by `templates/crate/source.html`, any other language wouldn't be highlighted in the browser.
+const FORCEABLE_LANGUAGES: &[&str] = &["rust", "ini", "markdown"];
+
+/// Validates a language requested with `?lang=` against `FORCEABLE_LANGUAGES`
+pub(super) fn known_language(language: &str) -> Option<&'static str> {
+    FORCEABLE_LANGUAGES
+        .iter()
+        .copied()
+        .find(|known| known.eq_ignore_ascii_case(language))
+}
+
+/// Returns the extension of the file name of `path`, if it has one
+fn file_extension(path: &str) -> Option<&str> {
+    Some(path.rsplit('/').next()?.rsplit_once('.')?.1)
+}
+
+/// Whether a file may be highlighted as the language it's written in, given the extensions
+/// configured in `Config::source_rendered_extensions`. Other files are shown as plain text.
+pub(super) fn rendering_allowed(allowed_extensions: Option<&[String]>, path: &str) -> bool {
+    allowed_extensions.map_or(true, |allowed| has_extension(allowed, path))
+}
+
+/// Whether the file name of `path` ends with one of `extensions`, ignoring case
+pub(super) fn has_extension(extensions: &[String], path: &str) -> bool {
+    file_extension(path).map_or(false, |extension| {
+        extensions
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(extension))
+    })
+}
+
+/// Detects the language of a file from its extension
+pub(super) fn language_for_path(path: &str) -> Option<&'static str> {
+    let extension = file_extension(path)?;
+    LANGUAGES
+        .iter()
+        .find(|(ext, _)| ext.eq_ignore_ascii_case(extension))
+        .map(|(_, language)| *language)
+}
+
+/// Removes a leading UTF-8 byte order mark, which would otherwise be rendered as part of the
+/// first line
+pub(super) fn strip_bom(mut content: String) -> String {
+    const BOM: char = '\u{feff}';
+    if content.starts_with(BOM) {
+        content.drain(..BOM.len_utf8());
+    }
+    content
+}
+
+/// Binary files up to this size are previewed as a hexdump instead of being downloaded
+pub(super) const MAX_HEXDUMP_SIZE: usize = 4 * 1024;
+
+/// Whether a binary file is small enough to be previewed as a hexdump. Images are left out,
+/// they're either shown as images or served as they are, as are files that are text despite
+/// their mime type.
+pub(super) fn is_hexdump_previewed(mime: &str, size: usize) -> bool {
+    size <= MAX_HEXDUMP_SIZE && !mime.starts_with("image/")
+}
+
+/// Formats bytes like `hexdump -C` does: the offset, then sixteen bytes in hex and the same
+/// bytes as ASCII, with `.` for anything that isn't printable
+pub(super) fn hexdump(content: &[u8]) -> String {
+    let mut dump = String::new();
+    for (index, chunk) in content.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let (first, second) = hex.split_at(hex.len().min(8));
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        dump.push_str(&format!(
+            "{:08x}  {:<23}  {:<23}  |{}|\n",
+            index * 16,
+            first.join(" "),
+            second.join(" "),
+            ascii,
+        ));
+    }
+    dump.push_str(&format!("{:08x}\n", content.len()));
+    dump
+}
+
+/// The image types shown on the source page instead of being downloaded
+pub(super) const PREVIEWED_IMAGE_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/svg+xml",
+];
+
+/// Reads the width and height of an image from its header, for the formats where that's cheap
+pub(super) fn image_dimensions(mime: &str, content: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| {
+        content
+            .get(at..at + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+    };
+    let be32 = |at: usize| {
+        content
+            .get(at..at + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    match mime {
+        // the IHDR chunk always comes first, right after the signature
+        "image/png" if content.starts_with(b"\x89PNG\r\n\x1a\n") => {
+            if content.get(12..16)? != b"IHDR" {
+                return None;
+            }
+            Some((be32(16)?, be32(20)?))
+        }
+        "image/gif" if content.starts_with(b"GIF8") => {
+            let size = content.get(6..10)?;
+            Some((
+                u16::from_le_bytes([size[0], size[1]]).into(),
+                u16::from_le_bytes([size[2], size[3]]).into(),
+            ))
+        }
+        // the size is part of the start of frame segment, which comes after any metadata
+        "image/jpeg" if content.starts_with(&[0xff, 0xd8]) => {
+            let mut offset = 2;
+            loop {
+                if *content.get(offset)? != 0xff {
+                    return None;
+                }
+                match *content.get(offset + 1)? {
+                    // padding before a marker
+                    0xff => offset += 1,
+                    // markers without a segment
+                    0x01 | 0xd0..=0xd7 => offset += 2,
+                    0xc0..=0xcf if !matches!(content[offset + 1], 0xc4 | 0xc8 | 0xcc) => {
+                        let height = be16(offset + 5)?;
+                        let width = be16(offset + 7)?;
+                        return Some((width.into(), height.into()));
+                    }
+                    _ => offset += 2 + usize::from(be16(offset + 2)?),
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// How many bytes of a file are inspected to guess whether it's text
+const SNIFF_LEN: usize = 8 * 1024;
+
+/// Guesses whether the content is text, by checking that its beginning is valid UTF-8 without
+/// any NUL bytes
+pub(super) fn looks_like_text(content: &[u8]) -> bool {
+    let sample = &content[..content.len().min(SNIFF_LEN)];
+    if sample.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        // the sample may end in the middle of a multi-byte character
+        Err(err) => err.error_len().is_none() && sample.len() == SNIFF_LEN,
+    }
+}
+
+/// Decodes a text file that isn't valid UTF-8. UTF-16 is recognized by its byte order mark,
+/// anything else is assumed to be Windows-1252, the superset of Latin-1 that most legacy files are
+/// written in. Returns the text and the name of its encoding, or `None` if the decoded text has
+/// control characters in it, which means it's not text after all.
+pub(super) fn transcode(content: &[u8]) -> Option<(String, &'static str)> {
+    let (text, encoding, had_errors) = encoding_rs::WINDOWS_1252.decode(content);
+    if had_errors
+        || text
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+    {
+        return None;
+    }
+    Some((text.into_owned(), encoding.name()))
+}
+
+/// Whether a mime type describes human-readable text
+fn is_text_mime(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/json" | "application/javascript" | "application/toml" | "application/xml"
+        )
+}
+
+/// Whether a mime type says nothing about the contents of a file, as stored for files whose type
+/// wasn't known when the release was uploaded. Specific types like `image/png` aren't generic.
+fn is_generic_mime(mime: &str) -> bool {
+    matches!(
+        mime,
+        "" | "application/octet-stream" | "binary/octet-stream" | "application/unknown"
+    )
+}
+
+/// Whether a source file is text. If its stored mime type contradicts its extension or is
+/// generic, neither can be trusted and the contents decide. The same goes for scripts, which are
+/// often stored as `application/x-sh` and the like, but are still shown as text.
+pub(super) fn is_text_file(path: &str, blob: &Blob, script: bool) -> bool {
+    let mismatch = !blob.is_empty() && mime_disagrees_with_extension(path, &blob.mime);
+    if mismatch || script || is_generic_mime(&blob.mime) {
+        looks_like_text(&blob.content)
+    } else {
+        blob.mime.starts_with("text")
+    }
+}
+
+/// Whether the stored mime type of a file disagrees with its extension on whether it's text, like
+/// a `.png` labeled as `text/plain` or a `.rs` file labeled as `application/octet-stream`
+pub(super) fn mime_disagrees_with_extension(path: &str, mime: &str) -> bool {
+    mime_guess::from_path(path)
+        .first_raw()
+        .map_or(false, |expected| {
+            is_text_mime(expected) != is_text_mime(mime)
+        })
+}
+
+/// The content types files are served with by their extension, regardless of the mime type stored
+/// for them. The stored ones like `text/rust` aren't registered, so browsers don't know what to do
+/// with them.
+const SERVED_MIME_TYPES: &[(&str, &str)] = &[
+    ("rs", "text/plain"),
+    ("toml", "text/plain"),
+    ("lock", "text/plain"),
+    ("ron", "text/plain"),
+    ("md", "text/markdown"),
+    ("json", "application/json"),
+];
+
+/// Returns the content type a file is served with if it's fixed by `SERVED_MIME_TYPES`
+pub(super) fn served_mime(path: &str) -> Option<&'static str> {
+    let extension = file_extension(path)?;
+    SERVED_MIME_TYPES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(extension))
+        .map(|(_, mime)| *mime)
+}
+
+/// The crate roots whose module declarations are scanned by `public_module_paths`
+pub(super) const CRATE_ROOTS: &[&str] = &["src/lib.rs", "src/main.rs"];
+
+/// The path of the module a Rust file of a library is, from the file's path in the standard crate
+/// layout: `src/lib.rs` is the crate root, `src/a/b.rs` and `src/a/b/mod.rs` are `a::b`. Binaries,
+/// build scripts, examples, tests and anything that isn't a valid module name aren't modules.
+fn module_path(file_path: &str) -> Option<Vec<&str>> {
+    let path = file_path.strip_prefix("src/")?.strip_suffix(".rs")?;
+    if path == "lib" {
+        return Some(Vec::new());
+    }
+
+    let mut modules: Vec<&str> = path.split('/').collect();
+    if modules[0] == "main" || modules[0] == "bin" || modules[0] == "lib" {
+        return None;
+    }
+    if modules.len() > 1 && modules.last() == Some(&"mod") {
+        modules.pop();
+    }
+    let is_identifier = |name: &&str| {
+        name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && name.chars().next().map_or(false, |c| !c.is_ascii_digit())
+    };
+    if modules.iter().all(is_identifier) && modules != ["mod"] {
+        Some(modules)
+    } else {
+        None
+    }
+}
+
+/// Links a Rust file to the documentation of its module if the release has documentation, see
+/// `module_path`. Like the detection of public modules, this is best-effort: files of private
+/// modules or ones moved with `#[path]` link to pages that don't exist.
+pub(super) fn docs_link(metadata: &MetaData, file_path: &str) -> Option<String> {
+    let target_name = metadata
+        .target_name
+        .as_deref()
+        .filter(|_| metadata.rustdoc_status)?;
+    let modules = module_path(file_path)?;
+    let mut link = format!(
+        "/{}/{}/{}/",
+        metadata.name,
+        encode_path_segment(&metadata.version),
+        target_name,
+    );
+    if !modules.is_empty() {
+        link.push_str(&modules.join("/"));
+        link.push_str("/index.html");
+    }
+    Some(link)
+}
+
+/// Returns the names of the modules declared as `pub mod name;` in a Rust source file.
+///
+/// This is a plain line-based scan, it doesn't understand `cfg`s, `#[path]` or modules declared
+/// inside other items.
+fn public_mod_declarations(source: &str) -> Vec<&str> {
+    source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("pub mod "))
+        .filter_map(|rest| rest.trim_end().strip_suffix(';'))
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_'))
+        .collect()
+}
+
+/// Best-effort detection of the files making up the public API of a crate: its crate roots and
+/// the files of the public modules they declare.
+pub(super) fn public_module_paths(
+    storage: &Storage,
+    name: &str,
+    version: &str,
+    archive_storage: bool,
+) -> HashSet<String> {
+    let mut paths = HashSet::new();
+    for root in CRATE_ROOTS {
+        let blob = match storage.fetch_source_file(name, version, root, archive_storage) {
+            Ok(blob) => blob,
+            Err(_) => continue,
+        };
+        paths.insert((*root).to_owned());
+
+        let source = String::from_utf8_lossy(&blob.content);
+        for module in public_mod_declarations(&source) {
+            paths.insert(format!("src/{}.rs", module));
+            paths.insert(format!("src/{}/mod.rs", module));
+        }
+    }
+    paths
+}
+
+/// Rust files larger than this aren't parsed for `?check=1`
+const MAX_SYNTAX_CHECK_SIZE: usize = 512 * 1024;
+
+/// Whether a Rust file parses, shown as a badge with `?check=1`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(super) enum SyntaxCheck {
+    Ok,
+    Error { line: usize, message: String },
+    TooLarge,
+}
+
+/// Parses a Rust file to check whether it's complete and well-formed
+pub(super) fn check_syntax(content: &str) -> SyntaxCheck {
+    if content.len() > MAX_SYNTAX_CHECK_SIZE {
+        return SyntaxCheck::TooLarge;
+    }
+
+    // To know the lines of spans, proc-macro2 keeps every input parsed on a thread for as long
+    // as the thread lives. The file is parsed on a thread of its own so that it's freed again,
+    // instead of piling up in the threads serving requests.
+    let content = content.to_owned();
+    std::thread::spawn(move || match syn::parse_file(&content) {
+        Ok(_) => SyntaxCheck::Ok,
+        Err(err) => {
+            let message = err.to_string();
+            // errors at the end of the input and unbalanced delimiters don't point to a token,
+            // but they mean that the file ends too early
+            let line = if message == "lex error" || message.starts_with("unexpected end of input") {
+                content.lines().count()
+            } else {
+                err.span().start().line
+            };
+            SyntaxCheck::Error { line, message }
+        }
+    })
+    .join()
+    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// A line of a rendered source file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct SourceLine {
+    /// The 1-based number of the line
+    number: usize,
+    /// If this line starts a foldable block, the number of the block's last line
+    fold_end: Option<usize>,
+    /// Whether this is the line requested with `?line=`, which is highlighted and scrolled to
+    target: bool,
+}
+
+/// Splits a rendered file into its lines.
+///
+/// In Rust files, blocks of at least two consecutive doc comment lines (`///` or `//!`) are
+/// marked as foldable, so they can be collapsed to declutter the code.
+pub(super) fn source_lines(content: &str, is_rust: bool) -> Vec<SourceLine> {
+    let mut lines: Vec<SourceLine> = (1..=content.lines().count())
+        .map(|number| SourceLine {
+            number,
+            fold_end: None,
+            target: false,
+        })
+        .collect();
+    if !is_rust {
+        return lines;
+    }
+
+    let is_doc_comment = |line: &str| {
+        let line = line.trim_start();
+        // `////` is a regular comment
+        (line.starts_with("///") && !line.starts_with("////")) || line.starts_with("//!")
+    };
+    let mut block_start = None;
+    // an extra line at the end closes a block that reaches the end of the file
+    for (idx, line) in content.lines().chain(std::iter::once("")).enumerate() {
+        match (is_doc_comment(line), block_start) {
+            (true, None) => block_start = Some(idx),
+            (false, Some(start)) => {
+                if idx - start >= 2 {
+                    lines[start].fold_end = Some(idx);
+                }
+                block_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    lines
+}
+
+/// Cuts `content` off after at most `limit` bytes, at the end of the last character that fits.
+/// Returns whether anything was cut off.
+pub(super) fn truncate_content(content: &mut String, limit: usize) -> bool {
+    if content.len() <= limit {
+        return false;
+    }
+
+    let mut end = limit;
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    content.truncate(end);
+    true
+}
+
+/// The number of lines of a text file. The last line counts whether or not it ends with a newline.
+pub(super) fn line_count(content: &str) -> usize {
+    content.lines().count()
+}
+
+/// The tables of a manifest that declare dependencies. Cargo still accepts the old spellings with
+/// underscores.
+const DEPENDENCY_TABLES: &[&str] = &[
+    "dependencies",
+    "dev-dependencies",
+    "build-dependencies",
+    "dev_dependencies",
+    "build_dependencies",
+];
+
+/// Parses the dependencies out of a `Cargo.toml`, including the ones of specific targets and of
+/// the workspace. Each is returned with a link to the crate it refers to, which is the one given
+/// with `package` for renamed dependencies. Manifests that can't be parsed or don't declare any
+/// dependencies return `None`, they are shown like any other file.
+pub(super) fn cargo_toml_deps(content: &str) -> Option<Vec<(String, String)>> {
+    let manifest: toml::Value = toml::from_str(content).ok()?;
+    let targets = manifest
+        .get("target")
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flat_map(|targets| targets.values());
+    let tables = std::iter::once(&manifest)
+        .chain(manifest.get("workspace"))
+        .chain(targets);
+
+    let mut deps = BTreeSet::new();
+    for table in tables {
+        for kind in DEPENDENCY_TABLES {
+            let entries = match table.get(*kind).and_then(toml::Value::as_table) {
+                Some(entries) => entries,
+                None => continue,
+            };
+            for (name, dependency) in entries {
+                let package = dependency
+                    .get("package")
+                    .and_then(toml::Value::as_str)
+                    .unwrap_or(name);
+                let is_crate_name = !package.is_empty()
+                    && package
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                if is_crate_name {
+                    deps.insert((name.clone(), format!("/crate/{}", package)));
+                }
+            }
+        }
+    }
+
+    if deps.is_empty() {
+        None
+    } else {
+        Some(deps.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::*;
+    use test_case::test_case;
+
+    #[test_case("src/lib.rs", Some(&[][..]))]
+    #[test_case("src/net.rs", Some(&["net"][..]))]
+    #[test_case("src/net/mod.rs", Some(&["net"][..]))]
+    #[test_case("src/net/tcp.rs", Some(&["net", "tcp"][..]))]
+    #[test_case("src/main.rs", None)]
+    #[test_case("src/bin/tool.rs", None)]
+    #[test_case("build.rs", None)]
+    #[test_case("examples/demo.rs", None)]
+    #[test_case("tests/it.rs", None)]
+    #[test_case("src/mod.rs", None)]
+    #[test_case("src/net-io.rs", None)]
+    #[test_case("src/2d.rs", None)]
+    #[test_case("src/README.md", None)]
+    fn module_paths(path: &str, expected: Option<&[&str]>) {
+        assert_eq!(super::module_path(path).as_deref(), expected);
+    }
+
+    #[test]
+    fn docs_link() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"pub mod net;")
+                .source_file("src/net/tcp.rs", b"fn foo() {}")
+                .source_file("examples/demo.rs", b"fn main() {}")
+                .create()?;
+            env.fake_release()
+                .name("tool")
+                .version("0.1.0")
+                .binary(true)
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/foo/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"<a href="/foo/0.1.0/foo/">"#));
+            let page = web
+                .get("/crate/foo/0.1.0/source/src/net/tcp.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"<a href="/foo/0.1.0/foo/net/tcp/index.html">"#));
+
+            // files that aren't modules, and releases without docs, have nothing to link to
+            for path in &[
+                "/crate/foo/0.1.0/source/examples/demo.rs",
+                "/crate/tool/0.1.0/source/src/lib.rs",
+            ] {
+                let page = web.get(*path).send()?.text()?;
+                assert!(!page.contains("Documentation of this module"), "{}", path);
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn truncate_content() {
+        let truncated = |content: &str, limit| {
+            let mut content = content.to_owned();
+            let truncated = super::truncate_content(&mut content, limit);
+            (content, truncated)
+        };
+        assert_eq!(truncated("hello", 5), ("hello".into(), false));
+        assert_eq!(truncated("hello", 3), ("hel".into(), true));
+        // `é` takes up bytes 1 and 2, it's never split
+        assert_eq!(truncated("héllo", 2), ("h".into(), true));
+        assert_eq!(truncated("héllo", 3), ("hé".into(), true));
+        assert_eq!(truncated("🦀", 3), ("".into(), true));
+    }
+
+    #[test]
+    fn cargo_toml_deps() {
+        let manifest = r#"
+            [package]
+            name = "fake"
+
+            [dependencies]
+            serde = "1"
+            json = { package = "serde_json", version = "1" }
+            "not a crate" = "1"
+
+            [dependencies.log]
+            version = "0.4"
+
+            [dev-dependencies]
+            test-case = "1"
+
+            [target.'cfg(unix)'.build-dependencies]
+            cc = "1"
+        "#;
+        assert_eq!(
+            super::cargo_toml_deps(manifest),
+            Some(vec![
+                ("cc".into(), "/crate/cc".into()),
+                ("json".into(), "/crate/serde_json".into()),
+                ("log".into(), "/crate/log".into()),
+                ("serde".into(), "/crate/serde".into()),
+                ("test-case".into(), "/crate/test-case".into()),
+            ])
+        );
+
+        assert_eq!(super::cargo_toml_deps("[package]\nname = \"fake\""), None);
+        assert_eq!(super::cargo_toml_deps("[dependencies\nserde ="), None);
+        assert_eq!(super::cargo_toml_deps("dependencies = 1"), None);
+
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("Cargo.toml", manifest.as_bytes())
+                .source_file("broken/Cargo.toml", b"[dependencies\nserde =")
+                .source_file("deps.toml", b"[dependencies]\nserde = \"1\"")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/Cargo.toml")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"<a href="/crate/serde">serde</a>"#));
+            assert!(page.contains(r#"<a href="/crate/serde_json">json</a>"#));
+
+            // anything else is just highlighted
+            for path in &["broken/Cargo.toml", "deps.toml"] {
+                let response = web
+                    .get(&format!("/crate/fake/0.1.0/source/{}", path))
+                    .send()?;
+                assert!(response.status().is_success());
+                assert!(!response.text()?.contains("cargo-dependencies"), "{}", path);
+            }
+            Ok(())
+        })
+    }
+
+    #[test_case("a", 1; "no trailing newline")]
+    #[test_case("a\n", 1; "trailing newline")]
+    #[test_case("a\nb\nc", 3; "several lines")]
+    #[test_case("a\n\n\n", 3; "empty trailing lines")]
+    #[test_case("a\r\nb\r\n", 2; "crlf")]
+    #[test_case("a\r\nb\r\n\n", 3; "crlf and empty trailing line")]
+    #[test_case("\n", 1; "single empty line")]
+    #[test_case("", 0; "empty")]
+    fn line_count(content: &str, count: usize) {
+        assert_eq!(super::line_count(content), count);
+        for &is_rust in &[true, false] {
+            let numbers: Vec<_> = super::source_lines(content, is_rust)
+                .into_iter()
+                .map(|line| line.number)
+                .collect();
+            assert_eq!(numbers, (1..=count).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn public_mod_declarations() {
+        assert_eq!(
+            super::public_mod_declarations(
+                "pub mod foo;\nmod bar;\n    pub mod baz ;\npub mod inline {}\npub(crate) mod qux;"
+            ),
+            ["foo", "baz"],
+        );
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn public_modules_are_tagged(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"pub mod foo;\nmod bar;")
+                .source_file("src/foo.rs", b"")
+                .source_file("src/bar.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web.get("/crate/fake/0.1.0/source/src/").send()?.text()?;
+            let tag = r#"<span class="public-module" title="Part of the public API">pub</span>"#;
+            for (file, public) in &[("lib.rs", true), ("foo.rs", true), ("bar.rs", false)] {
+                let listed = format!(r#"<span class="text">{}</span>{}"#, file, tag);
+                assert_eq!(page.contains(&listed), *public, "{}", file);
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn doc_comments_are_foldable() {
+        let source = "//! Crate docs\n//! more\n\n/// single line\nfn foo() {}\n\n    /// Docs\n    ///\n    /// end\n    //// not docs\n    fn bar() {}\n/// a\n/// b";
+        let folds: Vec<_> = super::source_lines(source, true)
+            .into_iter()
+            .filter_map(|line| line.fold_end.map(|end| (line.number, end)))
+            .collect();
+        assert_eq!(folds, [(1, 2), (7, 9), (12, 13)]);
+
+        assert!(super::source_lines(source, false)
+            .iter()
+            .all(|line| line.fold_end.is_none()));
+    }
+
+    #[test]
+    fn hexdump() {
+        assert_eq!(
+            super::hexdump(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0hi!\n"),
+            "00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|\n\
+             00000010  68 69 21 0a                                       |hi!.|\n\
+             00000014\n",
+        );
+        assert_eq!(super::hexdump(b""), "00000000\n");
+    }
+
+    #[test]
+    fn image_dimensions() {
+        use super::image_dimensions;
+
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 64, 0, 0, 0, 32]);
+        assert_eq!(image_dimensions("image/png", &png), Some((64, 32)));
+        assert_eq!(image_dimensions("image/png", &png[..20]), None);
+
+        assert_eq!(
+            image_dimensions("image/gif", b"GIF89a\x40\0\x20\0"),
+            Some((64, 32))
+        );
+
+        let jpeg = [
+            0xff, 0xd8, // start of image
+            0xff, 0xe0, 0, 4, 0, 0, // an application segment
+            0xff, 0xc0, 0, 11, 8, 0, 32, 0, 64, // start of frame
+        ];
+        assert_eq!(image_dimensions("image/jpeg", &jpeg), Some((64, 32)));
+
+        assert_eq!(image_dimensions("image/webp", b"RIFF"), None);
+        assert_eq!(image_dimensions("image/png", b"not a png"), None);
+    }
+
+    #[test]
+    fn check_syntax() {
+        use super::{check_syntax, SyntaxCheck};
+
+        assert_eq!(check_syntax("fn foo() {}"), SyntaxCheck::Ok);
+        assert!(matches!(
+            check_syntax("fn foo() {}\nstruct;\n"),
+            SyntaxCheck::Error { line: 2, .. }
+        ));
+        assert!(matches!(
+            check_syntax("fn foo() {}\nfn bar() {\n    let x = 1;\n"),
+            SyntaxCheck::Error { line: 3, .. }
+        ));
+        assert_eq!(
+            check_syntax(&"fn foo() {}\n".repeat(super::MAX_SYNTAX_CHECK_SIZE)),
+            SyntaxCheck::TooLarge
+        );
+    }
+
+    #[test]
+    fn transcode() {
+        use super::transcode;
+
+        assert_eq!(
+            transcode(b"na\xefve"),
+            Some(("naïve".to_owned(), "windows-1252"))
+        );
+        assert_eq!(
+            transcode(b"\xfe\xff\0o\0k"),
+            Some(("ok".to_owned(), "UTF-16BE"))
+        );
+        assert_eq!(transcode(b"\x89PNG\r\n\x1a\n\0"), None);
+    }
+}
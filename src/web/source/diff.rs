@@ -0,0 +1,754 @@
+//! Comparing the source of different releases
+
+use super::{
+    classify_path,
+    content::{has_extension, is_text_file, strip_bom, transcode},
+    directory_file_paths, invalid_source_path, query_param, set_nosniff, source_browsing_disabled,
+    PathKind, SourceRelease,
+};
+use crate::{
+    db::Pool,
+    error::Result,
+    impl_webpage,
+    storage::Blob,
+    web::{error::Nope, match_version, page::WebPage, ErrorPage},
+    Config, Storage,
+};
+use iron::{
+    headers::{CacheControl, CacheDirective, ContentType},
+    status,
+    url::percent_encoding::percent_decode,
+    IronResult, Request, Response,
+};
+use postgres::Client;
+use router::Router;
+use serde::Serialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// How an entry of a directory listing changed compared to another release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Change {
+    Unchanged,
+    Added,
+    Modified,
+}
+
+/// Maximum number of files whose contents are compared for a single `?changed-since=` listing,
+/// entries past the limit aren't annotated
+pub(super) const MAX_CHANGE_COMPARISONS: usize = 1000;
+
+/// The source of a single release, for comparisons between releases
+pub(super) struct ReleaseSource<'a> {
+    pub(super) version: &'a str,
+    pub(super) archive_storage: bool,
+    pub(super) files: &'a Value,
+}
+
+/// Determines how the file or directory at `path` changed from `previous` to `current`.
+///
+/// Every compared file counts against `budget`, `None` is returned once it's used up or if a file
+/// couldn't be loaded.
+pub(super) fn entry_change(
+    storage: &Storage,
+    name: &str,
+    current: &ReleaseSource<'_>,
+    previous: &ReleaseSource<'_>,
+    path: &str,
+    budget: &mut usize,
+) -> Option<Change> {
+    let paths = |release: &ReleaseSource<'_>| {
+        let mut paths = if path.ends_with('/') {
+            directory_file_paths(release.files, path, true)
+        } else if classify_path(release.files, path) == PathKind::File {
+            vec![path.to_owned()]
+        } else {
+            Vec::new()
+        };
+        paths.sort();
+        paths
+    };
+
+    let current_paths = paths(current);
+    let previous_paths = paths(previous);
+    if previous_paths.is_empty() {
+        return Some(Change::Added);
+    }
+    if current_paths != previous_paths {
+        return Some(Change::Modified);
+    }
+
+    for path in &current_paths {
+        *budget = budget.checked_sub(1)?;
+        let fetch = |release: &ReleaseSource<'_>| {
+            storage
+                .fetch_source_file(name, release.version, path, release.archive_storage)
+                .ok()
+        };
+        if fetch(current)?.content != fetch(previous)?.content {
+            return Some(Change::Modified);
+        }
+    }
+
+    Some(Change::Unchanged)
+}
+
+/// Maximum number of a crate's most recent releases that are checked for a file
+const MAX_FILE_VERSIONS_SCANNED: i64 = 1000;
+
+/// How long the versions containing a file can be cached, new releases might add to them
+const FILE_VERSIONS_CACHE_DURATION: u32 = 10 * 60;
+
+/// Returns the versions of a crate whose source contains `path`, newest version first, or `None`
+/// if the crate doesn't exist. Only the [`MAX_FILE_VERSIONS_SCANNED`] most recent releases are
+/// checked.
+fn file_versions(conn: &mut Client, name: &str, path: &str) -> Result<Option<Vec<String>>> {
+    if conn
+        .query_opt("SELECT id FROM crates WHERE name = $1", &[&name])?
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    let rows = conn.query(
+        "SELECT recent.version
+        FROM (
+            SELECT releases.version, releases.files
+            FROM releases
+            INNER JOIN crates ON crates.id = releases.crate_id
+            WHERE crates.name = $1
+            ORDER BY releases.release_time DESC
+            LIMIT $3
+        ) AS recent
+        WHERE EXISTS (
+            SELECT 1 FROM json_array_elements(recent.files) AS file WHERE file->>1 = $2
+        )",
+        &[&name, &path, &MAX_FILE_VERSIONS_SCANNED],
+    )?;
+
+    let mut versions: Vec<String> = rows.into_iter().map(|row| row.get(0)).collect();
+    // versions that aren't valid semver are sorted last
+    versions.sort_by(
+        |a, b| match (semver::Version::parse(a), semver::Version::parse(b)) {
+            (Ok(a), Ok(b)) => b.cmp(&a),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => a.cmp(b),
+        },
+    );
+    Ok(Some(versions))
+}
+
+/// Serves the versions of a crate containing a source file as a JSON array, for
+/// `/crate/:name/source/<path>/versions.json`
+pub fn file_versions_handler(req: &mut Request) -> IronResult<Response> {
+    set_nosniff(file_versions_response(req))
+}
+
+fn file_versions_response(req: &mut Request) -> IronResult<Response> {
+    if extension!(req, Config).source_browser_disable_path.exists() {
+        return source_browsing_disabled(req);
+    }
+
+    let name = cexpect!(req, extension!(req, Router).find("name"));
+    let mut req_path = req.url.path();
+    // remove the first elements from the path, which are /crate/:name/source
+    req_path.drain(0..3);
+    let path = match req_path.join("/").strip_suffix("/versions.json") {
+        Some(path) if !path.is_empty() => percent_decode(path.as_bytes())
+            .decode_utf8_lossy()
+            .into_owned(),
+        _ => return Err(Nope::ResourceNotFound.into()),
+    };
+
+    let mut conn = extension!(req, Pool).get()?;
+    let versions = match ctry!(req, file_versions(&mut conn, name, &path)) {
+        Some(versions) => versions,
+        None => return Err(Nope::CrateNotFound.into()),
+    };
+
+    let mut resp = Response::with((status::Ok, serde_json::to_string(&versions).unwrap()));
+    resp.headers.set(ContentType::json());
+    resp.headers.set(CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(FILE_VERSIONS_CACHE_DURATION),
+    ]));
+    Ok(resp)
+}
+
+/// The largest number of line pairs compared when diffing two files, once their common start and
+/// end are skipped. The table of the comparison grows with the changed lines of both files.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// The unchanged lines shown around each change of a diff
+const DIFF_CONTEXT: usize = 3;
+
+/// Whether a line of a diff was kept, added or removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum DiffKind {
+    Context,
+    Added,
+    Removed,
+}
+
+/// A line of a diff between two versions of a file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct DiffLine {
+    kind: DiffKind,
+    /// The 1-based number of the line in the old file, unless it was added
+    old_number: Option<usize>,
+    /// The 1-based number of the line in the new file, unless it was removed
+    new_number: Option<usize>,
+    content: String,
+}
+
+/// A row of a side-by-side diff, a removed line is shown next to the line that replaced it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct DiffRow {
+    old: Option<DiffLine>,
+    new: Option<DiffLine>,
+}
+
+/// Diffs two texts line by line, by their longest common subsequence. Returns `None` if the
+/// changed part is too large to be compared, see `MAX_DIFF_CELLS`.
+fn diff_lines(old: &str, new: &str) -> Option<Vec<DiffLine>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_changed = &old[prefix..old.len() - suffix];
+    let new_changed = &new[prefix..new.len() - suffix];
+    if old_changed.len().saturating_mul(new_changed.len()) > MAX_DIFF_CELLS {
+        return None;
+    }
+
+    // `lcs[i * width + j]` is the length of the longest common subsequence of `old_changed[i..]`
+    // and `new_changed[j..]`
+    let width = new_changed.len() + 1;
+    let mut lcs = vec![0u32; (old_changed.len() + 1) * width];
+    for i in (0..old_changed.len()).rev() {
+        for j in (0..new_changed.len()).rev() {
+            lcs[i * width + j] = if old_changed[i] == new_changed[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let line = |kind, old_index: Option<usize>, new_index: Option<usize>, content: &str| DiffLine {
+        kind,
+        old_number: old_index.map(|index| index + 1),
+        new_number: new_index.map(|index| index + 1),
+        content: content.to_owned(),
+    };
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    for (index, content) in old[..prefix].iter().enumerate() {
+        lines.push(line(DiffKind::Context, Some(index), Some(index), content));
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < old_changed.len() || j < new_changed.len() {
+        if i < old_changed.len() && j < new_changed.len() && old_changed[i] == new_changed[j] {
+            lines.push(line(
+                DiffKind::Context,
+                Some(prefix + i),
+                Some(prefix + j),
+                old_changed[i],
+            ));
+            i += 1;
+            j += 1;
+        } else if i < old_changed.len()
+            && (j == new_changed.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            lines.push(line(
+                DiffKind::Removed,
+                Some(prefix + i),
+                None,
+                old_changed[i],
+            ));
+            i += 1;
+        } else {
+            lines.push(line(
+                DiffKind::Added,
+                None,
+                Some(prefix + j),
+                new_changed[j],
+            ));
+            j += 1;
+        }
+    }
+    for offset in 0..suffix {
+        let old_index = old.len() - suffix + offset;
+        let new_index = new.len() - suffix + offset;
+        lines.push(line(
+            DiffKind::Context,
+            Some(old_index),
+            Some(new_index),
+            old[old_index],
+        ));
+    }
+    Some(lines)
+}
+
+/// Splits a diff into hunks of the changed lines with up to `DIFF_CONTEXT` unchanged lines around
+/// them, like `diff -u` does
+fn diff_hunks(lines: Vec<DiffLine>) -> Vec<Vec<DiffLine>> {
+    let mut shown = vec![false; lines.len()];
+    for (index, line) in lines.iter().enumerate() {
+        if line.kind != DiffKind::Context {
+            let end = (index + DIFF_CONTEXT).min(lines.len() - 1);
+            for shown in &mut shown[index.saturating_sub(DIFF_CONTEXT)..=end] {
+                *shown = true;
+            }
+        }
+    }
+
+    let mut hunks: Vec<Vec<DiffLine>> = Vec::new();
+    let mut previous_shown = false;
+    for (line, shown) in lines.into_iter().zip(shown) {
+        if shown {
+            match hunks.last_mut() {
+                Some(hunk) if previous_shown => hunk.push(line),
+                _ => hunks.push(vec![line]),
+            }
+        }
+        previous_shown = shown;
+    }
+    hunks
+}
+
+/// Lays out a hunk side by side, pairing each run of removed lines with the added lines following
+/// it
+fn diff_rows(hunk: &[DiffLine]) -> Vec<DiffRow> {
+    let mut rows = Vec::new();
+    let mut index = 0;
+    while index < hunk.len() {
+        if hunk[index].kind == DiffKind::Context {
+            rows.push(DiffRow {
+                old: Some(hunk[index].clone()),
+                new: Some(hunk[index].clone()),
+            });
+            index += 1;
+            continue;
+        }
+
+        let run = |from: usize, kind: DiffKind| {
+            hunk[from..]
+                .iter()
+                .take_while(|line| line.kind == kind)
+                .count()
+        };
+        let removed = &hunk[index..index + run(index, DiffKind::Removed)];
+        index += removed.len();
+        let added = &hunk[index..index + run(index, DiffKind::Added)];
+        index += added.len();
+        for row in 0..removed.len().max(added.len()) {
+            rows.push(DiffRow {
+                old: removed.get(row).cloned(),
+                new: added.get(row).cloned(),
+            });
+        }
+    }
+    rows
+}
+
+/// Decodes the contents of a text file, see `transcode` for files that aren't UTF-8
+fn decode_text(content: Vec<u8>) -> Option<String> {
+    match String::from_utf8(content) {
+        Ok(content) => Some(strip_bom(content)),
+        Err(err) => transcode(err.as_bytes()).map(|(content, _)| content),
+    }
+}
+
+/// Loads a source file of a release, or `None` if the release doesn't have it
+fn fetch_release_file(
+    conn: &mut Client,
+    storage: &Storage,
+    name: &str,
+    version: &str,
+    path: &str,
+) -> Result<Option<Blob>> {
+    let release = match SourceRelease::load(conn, name, version)? {
+        Some(release) => release,
+        None => return Ok(None),
+    };
+    if classify_path(release.file_list(), path) != PathKind::File {
+        return Ok(None);
+    }
+    storage
+        .fetch_source_file(name, version, path, release.archive_storage)
+        .map(Some)
+}
+
+/// The changes to a source file between two releases, see `source_diff_handler`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct SourceDiffPage {
+    name: String,
+    path: String,
+    from: String,
+    to: String,
+    /// Whether the file exists in `from` and in `to`, a file missing from one of them is shown
+    /// as completely added or removed
+    in_from: bool,
+    in_to: bool,
+    /// Whether either version of the file isn't text, those are only compared as a whole
+    binary: bool,
+    identical: bool,
+    /// Whether the changes were too large to be diffed, see `MAX_DIFF_CELLS`
+    too_large: bool,
+    added: usize,
+    removed: usize,
+    /// Whether the diff is shown side by side, as `split_hunks`, instead of as `hunks`
+    split: bool,
+    hunks: Vec<Vec<DiffLine>>,
+    split_hunks: Vec<Vec<DiffRow>>,
+}
+
+impl_webpage! {
+    SourceDiffPage = "crate/source_diff.html",
+}
+
+/// Shows the changes to a source file between two releases of a crate, for
+/// `/crate/:name/source-diff?from=<version>&to=<version>&path=<path>`. Either version may be a
+/// semver requirement. The diff is unified unless `?view=split` asks for it side by side.
+pub fn source_diff_handler(req: &mut Request) -> IronResult<Response> {
+    set_nosniff(source_diff_response(req))
+}
+
+fn source_diff_response(req: &mut Request) -> IronResult<Response> {
+    if extension!(req, Config).source_browser_disable_path.exists() {
+        return source_browsing_disabled(req);
+    }
+
+    let name = cexpect!(req, extension!(req, Router).find("name")).to_owned();
+    let (req_from, req_to, path) = match (
+        query_param(req, "from"),
+        query_param(req, "to"),
+        query_param(req, "path"),
+    ) {
+        (Some(from), Some(to), Some(path)) if !path.trim_matches('/').is_empty() => {
+            (from, to, path.trim_start_matches('/').to_owned())
+        }
+        _ => {
+            return ErrorPage {
+                title: "Missing parameters",
+                message: Some("the `from`, `to` and `path` parameters are required".into()),
+                status: status::BadRequest,
+            }
+            .into_response(req);
+        }
+    };
+    if let Some(reason) = invalid_source_path(&path) {
+        return ErrorPage {
+            title: "Invalid path",
+            message: Some(reason.into()),
+            status: status::BadRequest,
+        }
+        .into_response(req);
+    }
+
+    let mut conn = extension!(req, Pool).get()?;
+    let from_match = match_version(&mut conn, &name, Some(&req_from))?;
+    let name = from_match.corrected_name.clone().unwrap_or(name);
+    let (from, _) = from_match.version.into_parts();
+    let (to, _) = match_version(&mut conn, &name, Some(&req_to))?
+        .version
+        .into_parts();
+
+    let storage = extension!(req, Storage);
+    let script = has_extension(&extension!(req, Config).source_script_extensions, &path);
+    let old = ctry!(
+        req,
+        fetch_release_file(&mut conn, storage, &name, &from, &path)
+    );
+    let new = ctry!(
+        req,
+        fetch_release_file(&mut conn, storage, &name, &to, &path)
+    );
+    if old.is_none() && new.is_none() {
+        return Err(Nope::ResourceNotFound.into());
+    }
+
+    let (in_from, in_to) = (old.is_some(), new.is_some());
+    let is_text = |blob: &Option<Blob>| {
+        blob.as_ref()
+            .map_or(true, |blob| is_text_file(&path, blob, script))
+    };
+    let binary = !is_text(&old) || !is_text(&new);
+    let identical =
+        old.as_ref().map(|blob| &blob.content) == new.as_ref().map(|blob| &blob.content);
+    let texts = if binary || identical {
+        None
+    } else {
+        let decode =
+            |blob: Option<Blob>| blob.map_or(Some(String::new()), |blob| decode_text(blob.content));
+        decode(old).zip(decode(new))
+    };
+    // text that can't be decoded is compared like binary files
+    let binary = binary || (!identical && texts.is_none());
+
+    let lines = texts.and_then(|(old, new)| diff_lines(&old, &new));
+    let too_large = !binary && !identical && lines.is_none();
+    let lines = lines.unwrap_or_default();
+    let added = lines
+        .iter()
+        .filter(|line| line.kind == DiffKind::Added)
+        .count();
+    let removed = lines
+        .iter()
+        .filter(|line| line.kind == DiffKind::Removed)
+        .count();
+    let hunks = diff_hunks(lines);
+    let split = query_param(req, "view").as_deref() == Some("split");
+    let (hunks, split_hunks) = if split {
+        (
+            Vec::new(),
+            hunks.iter().map(|hunk| diff_rows(hunk)).collect(),
+        )
+    } else {
+        (hunks, Vec::new())
+    };
+
+    SourceDiffPage {
+        name,
+        path,
+        from,
+        to,
+        in_from,
+        in_to,
+        binary,
+        identical,
+        too_large,
+        added,
+        removed,
+        split,
+        hunks,
+        split_hunks,
+    }
+    .into_response(req)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::*;
+    use test_case::test_case;
+
+    #[test]
+    fn versions_containing_a_file() {
+        wrapper(|env| {
+            for (version, path) in &[
+                ("0.9.0", "src/lib.rs"),
+                ("0.10.0", "src/lib.rs"),
+                ("0.11.0", "src/main.rs"),
+            ] {
+                env.fake_release()
+                    .name("fake")
+                    .version(version)
+                    .source_file(path, b"fn foo() {}")
+                    .create()?;
+            }
+            let web = env.frontend();
+
+            let response = web
+                .get("/crate/fake/source/src/lib.rs/versions.json")
+                .send()?;
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.json::<Vec<String>>()?,
+                vec!["0.10.0".to_owned(), "0.9.0".to_owned()],
+            );
+
+            let response = web
+                .get("/crate/fake/source/src/missing.rs/versions.json")
+                .send()?;
+            assert_eq!(response.json::<Vec<String>>()?, Vec::<String>::new());
+
+            for path in &[
+                "/crate/missing/source/src/lib.rs/versions.json",
+                "/crate/fake/source/src/lib.rs",
+            ] {
+                assert_eq!(web.get(path).send()?.status(), 404, "{}", path);
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn diff_lines() {
+        use super::{DiffKind, DiffLine};
+
+        let summary =
+            |lines: Vec<DiffLine>| -> Vec<(DiffKind, Option<usize>, Option<usize>, String)> {
+                lines
+                    .into_iter()
+                    .map(|line| (line.kind, line.old_number, line.new_number, line.content))
+                    .collect()
+            };
+        assert_eq!(
+            summary(super::diff_lines("a\nb\nc\nd\n", "a\nB\nc\nd\ne\n").unwrap()),
+            [
+                (DiffKind::Context, Some(1), Some(1), "a".to_owned()),
+                (DiffKind::Removed, Some(2), None, "b".to_owned()),
+                (DiffKind::Added, None, Some(2), "B".to_owned()),
+                (DiffKind::Context, Some(3), Some(3), "c".to_owned()),
+                (DiffKind::Context, Some(4), Some(4), "d".to_owned()),
+                (DiffKind::Added, None, Some(5), "e".to_owned()),
+            ]
+        );
+
+        // a file that was added is added completely
+        assert_eq!(
+            summary(super::diff_lines("", "a\nb").unwrap()),
+            [
+                (DiffKind::Added, None, Some(1), "a".to_owned()),
+                (DiffKind::Added, None, Some(2), "b".to_owned()),
+            ]
+        );
+
+        let large: String = (0..3000).map(|line| format!("{}\n", line)).collect();
+        let reversed: String = (0..3000).rev().map(|line| format!("{}\n", line)).collect();
+        assert!(super::diff_lines(&large, &reversed).is_none());
+    }
+
+    #[test]
+    fn diff_hunks_and_rows() {
+        use super::DiffKind;
+
+        let old: String = (1..=20).map(|line| format!("{}\n", line)).collect();
+        let new: String = (1..=20)
+            .map(|line| match line {
+                2 => "two\n".to_owned(),
+                15 => "fifteen\n".to_owned(),
+                _ => format!("{}\n", line),
+            })
+            .collect();
+        let hunks = super::diff_hunks(super::diff_lines(&old, &new).unwrap());
+        let ranges: Vec<_> = hunks
+            .iter()
+            .map(|hunk| {
+                (
+                    hunk.first().unwrap().old_number,
+                    hunk.last().unwrap().old_number,
+                )
+            })
+            .collect();
+        assert_eq!(ranges, [(Some(1), Some(5)), (Some(12), Some(18))]);
+
+        let rows = super::diff_rows(&hunks[0]);
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[1].old.as_ref().unwrap().content, "2");
+        assert_eq!(rows[1].old.as_ref().unwrap().kind, DiffKind::Removed);
+        assert_eq!(rows[1].new.as_ref().unwrap().content, "two");
+        assert_eq!(rows[1].new.as_ref().unwrap().kind, DiffKind::Added);
+    }
+
+    #[test]
+    fn source_diff() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}\nfn bar() {}\n")
+                .source_file("src/old.rs", b"fn old() {}\n")
+                .source_file("logo.bin", b"\0\x01")
+                .create()?;
+            env.fake_release()
+                .name("fake")
+                .version("0.2.0")
+                .source_file("src/lib.rs", b"fn foo() {}\nfn baz() {}\n")
+                .source_file("src/new.rs", b"fn new() {}\n")
+                .source_file("logo.bin", b"\0\x02")
+                .create()?;
+            let web = env.frontend();
+            let diff = |query: &str| -> Result<(u16, String), anyhow::Error> {
+                let response = web
+                    .get(&format!("/crate/fake/source-diff?{}", query))
+                    .send()?;
+                Ok((response.status().as_u16(), response.text()?))
+            };
+
+            let (status, page) = diff("from=0.1.0&to=0.2.0&path=src/lib.rs")?;
+            assert_eq!(status, 200);
+            assert!(page.contains("<pre>-fn bar() {}</pre>"));
+            assert!(page.contains("<pre>+fn baz() {}</pre>"));
+            assert!(page.contains("<pre> fn foo() {}</pre>"));
+
+            // versions are resolved like everywhere else
+            let (_, page) = diff("from=0.1&to=*&path=src/lib.rs&view=split")?;
+            assert!(page.contains(r#"<td class="removed"><pre>fn bar() {}</pre></td>"#));
+            assert!(page.contains(r#"<td class="added"><pre>fn baz() {}</pre></td>"#));
+
+            let (_, page) = diff("from=0.1.0&to=0.2.0&path=src/new.rs")?;
+            assert!(page.contains("The file was added in 0.2.0."));
+            assert!(page.contains("<pre>+fn new() {}</pre>"));
+            let (_, page) = diff("from=0.1.0&to=0.2.0&path=src/old.rs")?;
+            assert!(page.contains("The file was removed in 0.2.0."));
+            assert!(page.contains("<pre>-fn old() {}</pre>"));
+
+            let (_, page) = diff("from=0.1.0&to=0.2.0&path=logo.bin")?;
+            assert!(page.contains("Binary files differ."));
+
+            assert_eq!(diff("from=0.1.0&to=0.2.0&path=src/missing.rs")?.0, 404);
+            assert_eq!(diff("from=0.1.0&to=0.3.0&path=src/lib.rs")?.0, 404);
+            assert_eq!(diff("from=0.1.0&path=src/lib.rs")?.0, 400);
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn changed_since(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/main.rs", b"fn main() {}")
+                .source_file("src/net/mod.rs", b"")
+                .source_file("src/io/mod.rs", b"")
+                .create()?;
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.2.0")
+                .source_file("src/lib.rs", b"fn bar() {}")
+                .source_file("src/main.rs", b"fn main() {}")
+                .source_file("src/new.rs", b"")
+                .source_file("src/net/mod.rs", b"")
+                .source_file("src/io/mod.rs", b"")
+                .source_file("src/io/file.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.2.0/source/src/?changed-since=0.1.0")
+                .send()?
+                .text()?;
+            for (name, change) in &[
+                ("lib.rs", "modified"),
+                ("main.rs", "unchanged"),
+                ("new.rs", "added"),
+                ("net", "unchanged"),
+                ("io", "modified"),
+            ] {
+                let marker = format!(
+                    r#"<span class="text">{}</span><span class="source-change source-change-{}">{}</span>"#,
+                    name, change, change
+                );
+                assert!(page.contains(&marker), "{} {}", name, change);
+            }
+
+            let page = web.get("/crate/fake/0.2.0/source/src/").send()?.text()?;
+            assert!(!page.contains("source-change"));
+            Ok(())
+        })
+    }
+}
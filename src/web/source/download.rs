@@ -0,0 +1,707 @@
+//! Downloading the source of a release
+
+use super::{
+    cache::SourceCaches, classify_path, directory_file_paths, invalid_source_path, range_header,
+    raw_file, resolve_source_release, set_immutable_cache_headers, set_nosniff,
+    source_browsing_disabled, PathKind, SourceRelease,
+};
+use crate::{db::Pool, error::Result, web::error::Nope, Config, Storage};
+use flate2::{write::GzEncoder, Compression};
+use iron::{
+    headers::{AcceptRanges, ContentType, RangeUnit},
+    response::WriteBody,
+    status, IronResult, Request, Response,
+};
+use postgres::Client;
+use router::Router;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    sync::Arc,
+};
+
+/// Maximum combined size of the files of a directory downloaded as a zip archive. The archive is
+/// built in memory before it's sent, see `directory_zip`, so this is kept well below the size of
+/// the whole source of large crates, which can be downloaded as a streamed tarball instead.
+pub(super) const MAX_DIRECTORY_DOWNLOAD_SIZE: usize = 20 * 1024 * 1024;
+
+/// Builds a zip archive of the given source files of a release.
+///
+/// The `zip` crate needs to seek in what it writes, so the archive is buffered in memory and only
+/// sent once it's complete. Returns `None` if the files exceed `MAX_DIRECTORY_DOWNLOAD_SIZE`.
+pub(super) fn directory_zip(
+    storage: &Storage,
+    name: &str,
+    version: &str,
+    archive_storage: bool,
+    paths: &[String],
+) -> Result<Option<Vec<u8>>> {
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+
+    let mut total_size = 0;
+    for path in paths {
+        let blob = storage.fetch_source_file(name, version, path, archive_storage)?;
+        total_size += blob.content.len();
+        if total_size > MAX_DIRECTORY_DOWNLOAD_SIZE {
+            return Ok(None);
+        }
+
+        zip.start_file(path.as_str(), options)?;
+        zip.write_all(&blob.content)?;
+    }
+
+    Ok(Some(zip.finish()?.into_inner()))
+}
+
+/// Maximum combined size of the files hashed for `source_checksums_handler`
+const MAX_CHECKSUMS_SIZE: usize = 100 * 1024 * 1024;
+
+/// Computes the SHA-256 checksums of the given source files of a release, keyed by path.
+///
+/// Returns `None` if the files exceed `MAX_CHECKSUMS_SIZE`.
+fn source_checksums(
+    storage: &Storage,
+    name: &str,
+    version: &str,
+    archive_storage: bool,
+    paths: &[String],
+) -> Result<Option<BTreeMap<String, String>>> {
+    let mut checksums = BTreeMap::new();
+
+    let mut total_size = 0;
+    for path in paths {
+        let blob = storage.fetch_source_file(name, version, path, archive_storage)?;
+        total_size += blob.content.len();
+        if total_size > MAX_CHECKSUMS_SIZE {
+            return Ok(None);
+        }
+
+        checksums.insert(path.clone(), format!("{:x}", Sha256::digest(&blob.content)));
+    }
+
+    Ok(Some(checksums))
+}
+
+/// Maximum number of paths that can be requested at once from `source_files_handler`
+const MAX_BATCH_FILES: usize = 20;
+/// Maximum combined size of the file contents returned by `source_files_handler`, files past the
+/// limit are reported as errors
+const MAX_BATCH_SIZE: usize = 5 * 1024 * 1024;
+
+/// The result of fetching a single file for `source_files_handler`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct BatchFile {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+impl BatchFile {
+    fn error(path: String, error: &'static str) -> Self {
+        Self {
+            path,
+            content: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Serves the contents of multiple source files of a release, given as repeated `path` query
+/// parameters, as a JSON array.
+///
+/// A path that can't be served doesn't fail the whole request, its entry carries an `error`
+/// instead of the `content`. Like in `source_browser`, paths are checked with
+/// `invalid_source_path` and against the file list of the release before the storage is asked.
+pub fn source_files_handler(req: &mut Request) -> IronResult<Response> {
+    set_nosniff(resolve_source_release(req, source_files))
+}
+
+fn source_files(req: &Request, name: &str, release: SourceRelease) -> IronResult<Response> {
+    let version = &release.metadata.version;
+    let paths: Vec<String> = req
+        .url
+        .as_ref()
+        .query_pairs()
+        .filter(|(key, _)| key == "path")
+        .map(|(_, path)| path.trim_start_matches('/').to_owned())
+        .collect();
+
+    if paths.len() > MAX_BATCH_FILES {
+        let body = json!({
+            "error": "too_many_paths",
+            "max_paths": MAX_BATCH_FILES,
+        });
+        let mut resp = Response::with((status::BadRequest, body.to_string()));
+        resp.headers.set(ContentType::json());
+        return Ok(resp);
+    }
+
+    let storage = extension!(req, Storage);
+    let archive_storage = release.archive_storage;
+    // releases without a file list can't be checked, see `source_browser`
+    let listed = release
+        .files
+        .as_ref()
+        .filter(|files| files.as_array().map_or(false, |files| !files.is_empty()));
+
+    let mut total_size = 0;
+    let files: Vec<BatchFile> = paths
+        .into_iter()
+        .map(|path| {
+            if invalid_source_path(&path).is_some() {
+                return BatchFile::error(path, "invalid_path");
+            }
+            if let Some(files) = listed {
+                if classify_path(files, &path) != PathKind::File {
+                    return BatchFile::error(path, "not_found");
+                }
+            }
+
+            let blob = match storage.fetch_source_file(name, version, &path, archive_storage) {
+                Ok(blob) => blob,
+                Err(_) => return BatchFile::error(path, "not_found"),
+            };
+
+            if !blob.mime.starts_with("text") && !blob.is_empty() {
+                return BatchFile::error(path, "not_text");
+            }
+            if total_size + blob.content.len() > MAX_BATCH_SIZE {
+                return BatchFile::error(path, "size_limit_exceeded");
+            }
+            total_size += blob.content.len();
+
+            match String::from_utf8(blob.content) {
+                Ok(content) => BatchFile {
+                    path,
+                    content: Some(content),
+                    error: None,
+                },
+                Err(_) => BatchFile::error(path, "not_text"),
+            }
+        })
+        .collect();
+
+    let mut resp = Response::with((status::Ok, serde_json::to_string(&files).unwrap()));
+    resp.headers.set(ContentType::json());
+    Ok(resp)
+}
+
+/// Serves the SHA-256 checksums of all source files of a release as a JSON object mapping each
+/// path to its hex-encoded hash, so a local checkout can be verified against it. Hashing the
+/// source of a large release takes a while, so the checksums are kept in `SourceCaches`.
+pub fn source_checksums_handler(req: &mut Request) -> IronResult<Response> {
+    set_nosniff(resolve_source_release(req, source_checksums_response))
+}
+
+fn source_checksums_response(
+    req: &Request,
+    name: &str,
+    release: SourceRelease,
+) -> IronResult<Response> {
+    let files = match &release.files {
+        Some(files) => files,
+        None => return Err(Nope::ResourceNotFound.into()),
+    };
+
+    let storage = extension!(req, Storage);
+    let version = &release.metadata.version;
+    let checksums = ctry!(
+        req,
+        extension!(req, SourceCaches)
+            .checksums
+            .get_or_try_insert_with(name, version, || {
+                let paths = directory_file_paths(files, "", true);
+                source_checksums(storage, name, version, release.archive_storage, &paths)
+            })
+    );
+    let checksums = match checksums {
+        Some(checksums) => checksums,
+        None => {
+            let body = json!({
+                "error": "size_limit_exceeded",
+                "max_size": MAX_CHECKSUMS_SIZE,
+            });
+            let mut resp = Response::with((status::BadRequest, body.to_string()));
+            resp.headers.set(ContentType::json());
+            return Ok(resp);
+        }
+    };
+
+    let mut resp = Response::with((status::Ok, serde_json::to_string(&checksums).unwrap()));
+    resp.headers.set(ContentType::json());
+    // the checksums of a release never change, so caches can keep them
+    set_immutable_cache_headers(&mut resp, name);
+    Ok(resp)
+}
+
+/// The number of a crate's most recent releases searched by `source_by_hash_handler`. The
+/// checksums of every release searched are computed, and then cached, so this is kept small.
+const MAX_HASH_LOOKUP_RELEASES: i64 = 20;
+
+/// The most recent releases of a crate with their file lists and whether they use archive
+/// storage, newest first. Returns `None` if the crate doesn't exist.
+fn recent_releases(
+    conn: &mut Client,
+    name: &str,
+) -> Result<Option<Vec<(String, Option<Value>, bool)>>> {
+    if conn
+        .query_opt("SELECT id FROM crates WHERE name = $1", &[&name])?
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    let rows = conn.query(
+        "SELECT releases.version, releases.files, releases.archive_storage
+        FROM releases
+        INNER JOIN crates ON crates.id = releases.crate_id
+        WHERE crates.name = $1
+        ORDER BY releases.release_time DESC
+        LIMIT $2",
+        &[&name, &MAX_HASH_LOOKUP_RELEASES],
+    )?;
+    Ok(Some(
+        rows.into_iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect(),
+    ))
+}
+
+/// Serves the source file of a crate whose content has the given SHA-256 hash, for
+/// `/source-by-hash/:name/:hash`. The content behind such a URL can never change, so it can be
+/// cited even when the file moves or the crate gets new releases.
+///
+/// The hashes are the ones of `source_checksums_handler`, the most recent releases having the
+/// file win. Only the `MAX_HASH_LOOKUP_RELEASES` most recent releases are searched.
+pub fn source_by_hash_handler(req: &mut Request) -> IronResult<Response> {
+    set_nosniff(source_by_hash(req))
+}
+
+fn source_by_hash(req: &Request) -> IronResult<Response> {
+    if extension!(req, Config).source_browser_disable_path.exists() {
+        return source_browsing_disabled(req);
+    }
+
+    let router = extension!(req, Router);
+    let name = cexpect!(req, router.find("name"));
+    let hash = cexpect!(req, router.find("hash")).to_ascii_lowercase();
+    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Nope::ResourceNotFound.into());
+    }
+
+    let mut conn = extension!(req, Pool).get()?;
+    let releases = match ctry!(req, recent_releases(&mut conn, name)) {
+        Some(releases) => releases,
+        None => return Err(Nope::CrateNotFound.into()),
+    };
+
+    let storage = extension!(req, Storage);
+    let caches = extension!(req, SourceCaches);
+    for (version, files, archive_storage) in releases {
+        let files = match files {
+            Some(files) => files,
+            None => continue,
+        };
+        let checksums = ctry!(
+            req,
+            caches.checksums.get_or_try_insert_with(name, &version, || {
+                let paths = directory_file_paths(&files, "", true);
+                source_checksums(storage, name, &version, archive_storage, &paths)
+            })
+        );
+
+        let path = checksums.and_then(|checksums| {
+            checksums
+                .into_iter()
+                .find(|(_, checksum)| *checksum == hash)
+                .map(|(path, _)| path)
+        });
+        if let Some(path) = path {
+            let blob = ctry!(
+                req,
+                storage.fetch_source_file(name, &version, &path, archive_storage)
+            );
+            let mut resp = raw_file(range_header(req), blob);
+            resp.headers
+                .set_raw("X-Docsrs-Resolved-Version", vec![version.into_bytes()]);
+            set_immutable_cache_headers(&mut resp, name);
+            return Ok(resp);
+        }
+    }
+
+    Err(Nope::ResourceNotFound.into())
+}
+
+/// Streams the source of a release as a gzipped tarball. Files are fetched one at a time while
+/// the response is written, so the archive is never held in memory as a whole.
+struct SourceTarball {
+    storage: Arc<Storage>,
+    name: String,
+    version: String,
+    archive_storage: bool,
+    paths: Vec<String>,
+}
+
+impl WriteBody for SourceTarball {
+    fn write_body(&mut self, res: &mut dyn Write) -> io::Result<()> {
+        // like in `.crate` files, everything is in a `<name>-<version>` directory
+        let prefix = format!("{}-{}", self.name, self.version);
+        let mut tar = tar::Builder::new(GzEncoder::new(res, Compression::default()));
+        for path in &self.paths {
+            let blob = self
+                .storage
+                .fetch_source_file(&self.name, &self.version, path, self.archive_storage)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+
+            let mut header = tar::Header::new_gnu();
+            header.set_size(blob.content.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(blob.date_updated.timestamp().max(0) as u64);
+            tar.append_data(
+                &mut header,
+                format!("{}/{}", prefix, path),
+                blob.content.as_slice(),
+            )?;
+        }
+        tar.into_inner()?.finish()?;
+
+        Ok(())
+    }
+}
+
+/// Serves all source files of a release as a gzipped tarball, see `SourceTarball`
+pub fn source_tarball_handler(req: &mut Request) -> IronResult<Response> {
+    set_nosniff(resolve_source_release(req, source_tarball))
+}
+
+fn source_tarball(req: &Request, name: &str, release: SourceRelease) -> IronResult<Response> {
+    let files = match &release.files {
+        Some(files) => files,
+        None => return Err(Nope::ResourceNotFound.into()),
+    };
+    let tarball = SourceTarball {
+        storage: extension!(req, Storage).clone(),
+        name: name.to_owned(),
+        archive_storage: release.archive_storage,
+        paths: directory_file_paths(files, "", true),
+        version: release.metadata.version.clone(),
+    };
+
+    let filename = format!("{}-{}.tar.gz", tarball.name, tarball.version);
+    let mut resp = Response::with((status::Ok, Box::new(tarball) as Box<dyn WriteBody>));
+    resp.headers
+        .set(ContentType("application/gzip".parse().unwrap()));
+    resp.headers.set_raw(
+        "Content-Disposition",
+        vec![format!("attachment; filename=\"{}\"", filename).into_bytes()],
+    );
+    // the archive is built while it's sent, and its bytes may differ between builds of docs.rs,
+    // so a range of it can't be resumed from and any `Range` header is ignored
+    resp.headers.set(AcceptRanges(vec![RangeUnit::None]));
+    set_immutable_cache_headers(&mut resp, name);
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{storage::Blob, test::*};
+    use chrono::{TimeZone, Utc};
+    use sha2::{Digest, Sha256};
+    use test_case::test_case;
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn fetch_multiple_files(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"mod net;")
+                .source_file("src/net.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web
+                .get("/crate/fake/0.1.0/source-files.json?path=src/lib.rs&path=src/missing.rs&path=src/net.rs")
+                .send()?;
+            assert!(response.status().is_success());
+            assert_eq!(
+                response.json::<serde_json::Value>()?,
+                serde_json::json!([
+                    { "path": "src/lib.rs", "content": "mod net;" },
+                    { "path": "src/missing.rs", "error": "not_found" },
+                    { "path": "src/net.rs", "content": "fn foo() {}" },
+                ]),
+            );
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn fetch_multiple_files_checks_paths(archive_storage: bool) {
+        wrapper(|env| {
+            let id = env
+                .fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/orphan.rs", b"fn bar() {}")
+                .create()?;
+            env.db().conn().execute(
+                r#"UPDATE releases SET files = '[["text/rust", "src/lib.rs"]]' WHERE id = $1"#,
+                &[&id],
+            )?;
+            env.fake_release()
+                .name("other")
+                .version("0.1.0")
+                .source_file("secret.rs", b"const SECRET: u32 = 42;")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web
+                .get("/crate/fake/0.1.0/source-files.json?path=src/lib.rs&path=src/orphan.rs&path=../../other/0.1.0/secret.rs&path=src/lib.rs%00.png")
+                .send()?;
+            assert!(response.status().is_success());
+            assert_eq!(
+                response.json::<serde_json::Value>()?,
+                serde_json::json!([
+                    { "path": "src/lib.rs", "content": "fn foo() {}" },
+                    { "path": "src/orphan.rs", "error": "not_found" },
+                    { "path": "../../other/0.1.0/secret.rs", "error": "invalid_path" },
+                    { "path": "src/lib.rs\u{0}.png", "error": "invalid_path" },
+                ]),
+            );
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn download_directory_as_zip(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("README.md", b"hello")
+                .source_file("src/lib.rs", b"mod net;")
+                .source_file("src/net/tcp.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let zip_entries = |path: &str| -> crate::error::Result<Vec<String>> {
+                let response = web.get(path).send()?;
+                assert!(response.status().is_success());
+                assert_eq!(response.headers()["Content-Type"], "application/zip");
+                let mut zip = zip::ZipArchive::new(std::io::Cursor::new(response.bytes()?))?;
+                let mut entries = (0..zip.len())
+                    .map(|i| Ok(zip.by_index(i)?.name().to_owned()))
+                    .collect::<crate::error::Result<Vec<_>>>()?;
+                entries.sort();
+                Ok(entries)
+            };
+
+            assert_eq!(
+                zip_entries("/crate/fake/0.1.0/source/src/?download=zip")?,
+                vec!["src/lib.rs"],
+            );
+            assert_eq!(
+                zip_entries("/crate/fake/0.1.0/source/src/?download=zip&recursive=1")?,
+                vec!["src/lib.rs", "src/net/tcp.rs"],
+            );
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn download_source_tarball(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("Cargo.toml", b"[package]")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file(".cargo-ok", b"ok")
+                .create()?;
+            let web = env.frontend();
+
+            assert_redirect(
+                "/crate/fake/0/source.tar.gz",
+                "/crate/fake/0.1.0/source.tar.gz",
+                web,
+            )?;
+
+            let response = web.get("/crate/fake/0.1.0/source.tar.gz").send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["Content-Type"], "application/gzip");
+            assert_eq!(
+                response.headers()["Content-Disposition"],
+                "attachment; filename=\"fake-0.1.0.tar.gz\""
+            );
+
+            assert_eq!(response.headers()["Accept-Ranges"], "none");
+
+            // ranges aren't supported, the whole tarball is sent
+            let response = web
+                .get("/crate/fake/0.1.0/source.tar.gz")
+                .header("Range", "bytes=0-9")
+                .send()?;
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.headers()["Accept-Ranges"], "none");
+            assert!(response.headers().get("Content-Range").is_none());
+
+            let mut tarball = tar::Archive::new(flate2::read::GzDecoder::new(
+                std::io::Cursor::new(response.bytes()?),
+            ));
+            let mut entries = Vec::new();
+            for entry in tarball.entries()? {
+                let mut entry = entry?;
+                let mut content = String::new();
+                std::io::Read::read_to_string(&mut entry, &mut content)?;
+                entries.push((entry.path()?.to_string_lossy().into_owned(), content));
+            }
+            entries.sort();
+            assert_eq!(
+                entries,
+                vec![
+                    ("fake-0.1.0/Cargo.toml".to_owned(), "[package]".to_owned()),
+                    ("fake-0.1.0/src/lib.rs".to_owned(), "fn foo() {}".to_owned()),
+                ]
+            );
+
+            let response = web.get("/crate/fake/0.2.0/source.tar.gz").send()?;
+            assert_eq!(response.status(), 404);
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn checksums_manifest(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("README.md", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/checksums.json").send()?;
+            assert!(response.status().is_success());
+            let checksums = response.json::<serde_json::Value>()?;
+            assert_eq!(
+                checksums["src/lib.rs"],
+                "946a0a9b72211dc6aa777c30b51e5f8263162e350d905a36b392c832ff3a3baf"
+            );
+            assert_eq!(
+                checksums["README.md"],
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+            );
+
+            assert_redirect(
+                "/crate/fake/0.1/source/checksums.json",
+                "/crate/fake/0.1.0/source/checksums.json",
+                web,
+            )?;
+            assert_not_found("/crate/fake/0.2.0/source/checksums.json", web)?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn checksums_are_cached() {
+        wrapper(|env| {
+            env.override_config(|config| config.source_release_cache_capacity = 10);
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let checksums = web
+                .get("/crate/fake/0.1.0/source/checksums.json")
+                .send()?
+                .json::<serde_json::Value>()?;
+
+            // a changed file in storage goes unnoticed, the cached checksums are served
+            env.storage().store_blobs(vec![Blob {
+                path: "sources/fake/0.1.0/src/lib.rs".into(),
+                mime: "text/rust".into(),
+                date_updated: Utc::now(),
+                content: b"fn bar() {}".to_vec(),
+                compression: None,
+            }])?;
+            let cached = web
+                .get("/crate/fake/0.1.0/source/checksums.json")
+                .send()?
+                .json::<serde_json::Value>()?;
+            assert_eq!(cached, checksums);
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn source_by_hash(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .release_time(Utc.ymd(2020, 1, 1).and_hms(0, 0, 0))
+                .source_file("src/old.rs", b"fn old() {}")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.2.0")
+                .release_time(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0))
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let hash = format!("{:x}", Sha256::digest(b"fn foo() {}"));
+            let response = web.get(&format!("/source-by-hash/fake/{}", hash)).send()?;
+            assert_eq!(response.status(), 200);
+            // the newest release with the file is served
+            assert_eq!(response.headers()["X-Docsrs-Resolved-Version"], "0.2.0");
+            assert!(response.headers()["Cache-Control"]
+                .to_str()?
+                .contains("immutable"));
+            assert_eq!(response.text()?, "fn foo() {}");
+
+            // files only older releases have are found too, and upper case hashes work
+            let hash = format!("{:X}", Sha256::digest(b"fn old() {}"));
+            let response = web.get(&format!("/source-by-hash/fake/{}", hash)).send()?;
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.headers()["X-Docsrs-Resolved-Version"], "0.1.0");
+            assert_eq!(response.text()?, "fn old() {}");
+
+            let unknown = format!("{:x}", Sha256::digest(b"fn bar() {}"));
+            for path in &[
+                format!("/source-by-hash/fake/{}", unknown),
+                format!("/source-by-hash/missing/{}", hash),
+                "/source-by-hash/fake/not-a-hash".to_owned(),
+            ] {
+                assert_eq!(web.get(path).send()?.status(), 404, "{}", path);
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,1759 @@
+//! Directory listings
+
+use super::{content::language_for_path, diff::Change, SourceRelease};
+use crate::{
+    error::Result,
+    web::{render_markdown, MetaData},
+};
+use iron::{url::form_urlencoded, Request};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+};
+
+/// A source file's name and mime type
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Serialize)]
+pub(super) struct File {
+    /// The name of the file
+    pub(super) name: String,
+    /// The mime type of the file
+    pub(super) mime: String,
+    /// The size of the file in bytes, or of all files in a directory. Unknown for releases from
+    /// before sizes were stored.
+    size: Option<u64>,
+    /// Whether this is a crate root or a public module declared in one, see
+    /// `public_module_paths`
+    pub(super) public: bool,
+    /// How the file changed since the release given with `?changed-since=`
+    pub(super) change: Option<Change>,
+    /// What kind of file this is, see `file_category`
+    category: FileCategory,
+}
+
+/// The kind of an entry of a directory listing, which decides its icon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum FileCategory {
+    Directory,
+    Source,
+    Config,
+    Doc,
+    Image,
+    /// Text that isn't any of the above
+    Text,
+    Binary,
+}
+
+/// The categories of files by their (lowercase) extension
+const CATEGORY_EXTENSIONS: &[(&str, FileCategory)] = &[
+    ("rs", FileCategory::Source),
+    ("c", FileCategory::Source),
+    ("h", FileCategory::Source),
+    ("cc", FileCategory::Source),
+    ("cpp", FileCategory::Source),
+    ("hpp", FileCategory::Source),
+    ("s", FileCategory::Source),
+    ("asm", FileCategory::Source),
+    ("py", FileCategory::Source),
+    ("js", FileCategory::Source),
+    ("ts", FileCategory::Source),
+    ("sh", FileCategory::Source),
+    ("toml", FileCategory::Config),
+    ("lock", FileCategory::Config),
+    ("json", FileCategory::Config),
+    ("yml", FileCategory::Config),
+    ("yaml", FileCategory::Config),
+    ("ini", FileCategory::Config),
+    ("cfg", FileCategory::Config),
+    ("md", FileCategory::Doc),
+    ("markdown", FileCategory::Doc),
+    ("rst", FileCategory::Doc),
+    ("adoc", FileCategory::Doc),
+    ("txt", FileCategory::Doc),
+    ("png", FileCategory::Image),
+    ("jpg", FileCategory::Image),
+    ("jpeg", FileCategory::Image),
+    ("gif", FileCategory::Image),
+    ("svg", FileCategory::Image),
+    ("webp", FileCategory::Image),
+    ("ico", FileCategory::Image),
+    ("bmp", FileCategory::Image),
+];
+
+/// The categories of files without a known extension by the beginning of their name, like
+/// `LICENSE-MIT`
+const CATEGORY_NAME_PREFIXES: &[(&str, FileCategory)] = &[
+    ("LICENSE", FileCategory::Doc),
+    ("LICENCE", FileCategory::Doc),
+    ("COPYING", FileCategory::Doc),
+    ("README", FileCategory::Doc),
+    ("CHANGELOG", FileCategory::Doc),
+    ("AUTHORS", FileCategory::Doc),
+    // dotfiles like `.gitignore` configure tools
+    (".", FileCategory::Config),
+];
+
+/// The categories of all other files by the beginning of their mime type
+const CATEGORY_MIME_PREFIXES: &[(&str, FileCategory)] = &[
+    ("text/rust", FileCategory::Source),
+    ("text/x-", FileCategory::Source),
+    ("text/markdown", FileCategory::Doc),
+    ("text/", FileCategory::Text),
+    ("image/", FileCategory::Image),
+    ("application/json", FileCategory::Config),
+    ("application/toml", FileCategory::Config),
+];
+
+/// Categorizes an entry of a directory listing by its extension, then its name and then its mime
+/// type. Anything unknown is binary.
+fn file_category(name: &str, mime: &str) -> FileCategory {
+    if mime == "dir" {
+        return FileCategory::Directory;
+    }
+
+    // a leading dot starts a dotfile's name, it doesn't separate an extension
+    let extension = name
+        .rsplit_once('.')
+        .filter(|(stem, _)| !stem.is_empty())
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    if let Some(extension) = extension {
+        if let Some((_, category)) = CATEGORY_EXTENSIONS
+            .iter()
+            .find(|(known, _)| *known == extension)
+        {
+            return *category;
+        }
+    }
+
+    CATEGORY_NAME_PREFIXES
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .or_else(|| {
+            CATEGORY_MIME_PREFIXES
+                .iter()
+                .find(|(prefix, _)| mime.starts_with(prefix))
+        })
+        .map_or(FileCategory::Binary, |(_, category)| *category)
+}
+
+/// A list of source files
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct FileList {
+    pub(super) metadata: MetaData,
+    pub(super) files: Vec<File>,
+    /// Whether directories were left out of `files`, see `MAX_LISTED_DIRECTORIES`
+    pub(super) truncated: bool,
+    /// The number of entries on all pages of the listing, see `ListingPage`
+    pub(super) total: usize,
+}
+
+/// Maximum number of entries shown on one page of a directory listing
+const LISTING_PAGE_SIZE: usize = 100;
+
+/// The slice of a directory listing returned by `FileList::from_path`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ListingPage {
+    offset: usize,
+    limit: usize,
+}
+
+impl ListingPage {
+    /// The 1-based page `number` of `LISTING_PAGE_SIZE` entries
+    pub(super) fn numbered(number: usize) -> Self {
+        ListingPage {
+            offset: number.saturating_sub(1) * LISTING_PAGE_SIZE,
+            limit: LISTING_PAGE_SIZE,
+        }
+    }
+}
+
+/// Maximum number of distinct directories listed at one level, the ones past it are left out
+pub(super) const MAX_LISTED_DIRECTORIES: usize = 10_000;
+
+/// Listings with more entries than this are split into sections by the kind of their entries,
+/// unless `?group=0` is given
+pub(super) const GROUPED_LISTING_SIZE: usize = 50;
+
+/// A section of a grouped directory listing, see `listing_groups`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct ListingGroup {
+    title: &'static str,
+    files: Vec<File>,
+}
+
+/// Splits the entries of a listing into directories, Rust files and all other files, keeping
+/// their order. Empty sections are left out.
+pub(super) fn listing_groups(files: &[File]) -> Vec<ListingGroup> {
+    let mut groups = vec![
+        ListingGroup {
+            title: "Directories",
+            files: Vec::new(),
+        },
+        ListingGroup {
+            title: "Rust files",
+            files: Vec::new(),
+        },
+        ListingGroup {
+            title: "Other files",
+            files: Vec::new(),
+        },
+    ];
+    for file in files {
+        let group = if file.mime == "dir" {
+            0
+        } else if file.mime == "text/rust" || file.name.ends_with(".rs") {
+            1
+        } else {
+            2
+        };
+        groups[group].files.push(file.clone());
+    }
+    groups.retain(|group| !group.files.is_empty());
+    groups
+}
+
+/// The order in which the entries of a directory listing are shown. Directories are always
+/// listed first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ListingSort {
+    /// By name
+    Name,
+    /// Like `Name`, but the well-known entries of a crate root are shown first, in the order of
+    /// `CONVENTIONAL_ROOT_ORDER`
+    Convention,
+    /// By mime type, then by name
+    Type,
+    /// By size, then by name. Entries of unknown size are listed after the others.
+    Size,
+}
+
+/// A `ListingSort` and its direction, chosen with `?sort=` and `?dir=asc|desc`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(super) struct ListingOrder {
+    sort: ListingSort,
+    descending: bool,
+}
+
+impl ListingOrder {
+    pub(super) fn from_query(sort: Option<&str>, dir: Option<&str>) -> Self {
+        let sort = match sort {
+            Some("convention") => ListingSort::Convention,
+            Some("type") => ListingSort::Type,
+            Some("size") => ListingSort::Size,
+            _ => ListingSort::Name,
+        };
+        ListingOrder {
+            sort,
+            descending: dir == Some("desc"),
+        }
+    }
+
+    /// Compares two entries of the same directory
+    fn compare(self, a: &File, b: &File, is_root: bool) -> Ordering {
+        // names that only differ in case are distinct entries, they're ordered by their
+        // case-sensitive name so the listing doesn't depend on the stored order
+        let by_name = |a: &File, b: &File| {
+            a.name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then_with(|| a.name.cmp(&b.name))
+        };
+
+        if self.sort == ListingSort::Convention && is_root {
+            let rank = |file: &File| {
+                CONVENTIONAL_ROOT_ORDER
+                    .iter()
+                    .position(|name| *name == file.name)
+                    .unwrap_or(CONVENTIONAL_ROOT_ORDER.len())
+            };
+            match rank(a).cmp(&rank(b)) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            }
+        }
+
+        // directories must be listed first
+        if a.mime == "dir" && b.mime != "dir" {
+            return Ordering::Less;
+        } else if a.mime != "dir" && b.mime == "dir" {
+            return Ordering::Greater;
+        }
+
+        let ordering = match self.sort {
+            ListingSort::Name | ListingSort::Convention => by_name(a, b),
+            ListingSort::Type => a.mime.cmp(&b.mime).then_with(|| by_name(a, b)),
+            ListingSort::Size => match (a.size, b.size) {
+                (Some(a_size), Some(b_size)) => a_size.cmp(&b_size).then_with(|| by_name(a, b)),
+                (Some(_), None) => return Ordering::Less,
+                (None, Some(_)) => return Ordering::Greater,
+                (None, None) => by_name(a, b),
+            },
+        };
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+impl From<ListingSort> for ListingOrder {
+    fn from(sort: ListingSort) -> Self {
+        ListingOrder {
+            sort,
+            descending: false,
+        }
+    }
+}
+
+/// The familiar layout of a crate root, used by `ListingSort::Convention`
+const CONVENTIONAL_ROOT_ORDER: &[&str] = &[
+    "Cargo.toml",
+    "Cargo.lock",
+    "README.md",
+    "build.rs",
+    "src",
+    "examples",
+    "tests",
+    "benches",
+];
+
+impl FileList {
+    /// Gets FileList from a request path
+    ///
+    /// All paths stored in database have this format:
+    ///
+    /// ```text
+    /// [
+    ///   ["text/plain", ".gitignore", 27],
+    ///   ["text/x-c", "src/reseeding.rs", 5423],
+    ///   ["text/x-c", "src/lib.rs", 8132],
+    ///   ["text/x-c", "README.md", 942],
+    ///   ...
+    /// ]
+    /// ```
+    ///
+    /// The size is missing in the entries of older releases.
+    ///
+    /// This function is only returning FileList for requested directory. If is empty,
+    /// it will return list of files (and dirs) for root directory. req_path must be a
+    /// directory or empty for root directory.
+    ///
+    /// If `dirs_only` is set, only the subdirectories of the requested directory are returned.
+    /// Only the first `MAX_LISTED_DIRECTORIES` subdirectories in the sorted order are returned. If
+    /// a `page` is given, only that slice of the sorted entries is returned. Files and directories
+    /// whose name starts with a dot are left out unless `show_hidden` is set.
+    pub(super) fn from_path(
+        release: &SourceRelease,
+        req_path: &str,
+        dirs_only: bool,
+        show_hidden: bool,
+        order: ListingOrder,
+        page: Option<ListingPage>,
+    ) -> Option<FileList> {
+        let (name, version) = (&release.metadata.name, &release.metadata.version);
+        let files = release.files.as_ref()?;
+
+        let mut file_list = Vec::new();
+        // whether the directory contains files which aren't listed, so it exists even if the
+        // list ends up empty
+        let mut has_hidden_files = false;
+        if let Some(files) = files.as_array() {
+            file_list.reserve(files.len());
+            let mut seen_paths = HashSet::with_capacity(files.len());
+            // the size of each listed directory, if the sizes of all files in it are known
+            let mut dir_sizes: HashMap<String, Option<u64>> = HashMap::new();
+
+            for file in files {
+                if let Some(file) = file.as_array() {
+                    let mime = file[0].as_str().unwrap();
+                    let path = file[1].as_str().unwrap();
+                    let size = file.get(2).and_then(Value::as_u64);
+
+                    // the first entry of a path wins, later ones are corrupt data. Paths are
+                    // compared case-sensitively, like the file systems crates are built on.
+                    if !seen_paths.insert(path) {
+                        log::warn!(
+                            "duplicate path '{}' in the files of {} {}",
+                            path,
+                            name,
+                            version
+                        );
+                        continue;
+                    }
+
+                    // skip .cargo-ok generated by cargo
+                    if path == ".cargo-ok" {
+                        has_hidden_files |= path.starts_with(&req_path);
+                        continue;
+                    }
+
+                    // look only files for req_path
+                    if path.starts_with(&req_path) {
+                        // remove req_path from path to reach files in this directory
+                        let path = path.replace(&req_path, "");
+                        let path_splited: Vec<&str> = path.split('/').collect();
+
+                        if !show_hidden && path_splited[0].starts_with('.') {
+                            has_hidden_files = true;
+                            continue;
+                        }
+
+                        // if path have '/' it is a directory
+                        let mime = if path_splited.len() > 1 {
+                            // avoid adding duplicates, a directory may occur more than once
+                            if let Some(total) = dir_sizes.get_mut(path_splited[0]) {
+                                *total = total.zip(size).map(|(total, size)| total + size);
+                                continue;
+                            }
+                            dir_sizes.insert(path_splited[0].to_owned(), size);
+                            "dir".to_owned()
+                        } else {
+                            mime.to_owned()
+                        };
+
+                        file_list.push(File {
+                            name: path_splited[0].to_owned(),
+                            category: file_category(path_splited[0], &mime),
+                            mime,
+                            size,
+                            public: false,
+                            change: None,
+                        });
+                    }
+                }
+            }
+
+            if file_list.is_empty() && !has_hidden_files {
+                return None;
+            }
+
+            for file in &mut file_list {
+                if let Some(size) = dir_sizes.get(&file.name).filter(|_| file.mime == "dir") {
+                    file.size = *size;
+                }
+            }
+
+            if dirs_only {
+                file_list.retain(|file| file.mime == "dir");
+            }
+
+            file_list.sort_by(|a, b| order.compare(a, b, req_path.is_empty()));
+
+            // capped only after sorting, so the directories left out don't depend on the stored
+            // order either
+            let mut listed_directories = 0;
+            file_list.retain(|file| {
+                if file.mime == "dir" {
+                    listed_directories += 1;
+                }
+                file.mime != "dir" || listed_directories <= MAX_LISTED_DIRECTORIES
+            });
+            let truncated = listed_directories > MAX_LISTED_DIRECTORIES;
+
+            // sliced only after sorting, so the pages don't depend on the stored order
+            let total = file_list.len();
+            if let Some(page) = page {
+                file_list = file_list
+                    .into_iter()
+                    .skip(page.offset)
+                    .take(page.limit)
+                    .collect();
+            }
+
+            Some(FileList {
+                metadata: release.metadata.clone(),
+                files: file_list,
+                truncated,
+                total,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// The names of the READMEs shown below a directory listing in order of preference, compared
+/// case-insensitively
+const README_NAMES: &[&str] = &["readme.md", "readme.markdown", "readme", "readme.txt"];
+
+/// Picks the README of a directory to show below its listing, see `README_NAMES`
+pub(super) fn directory_readme_name(files: &[File]) -> Option<&str> {
+    README_NAMES.iter().find_map(|readme| {
+        files
+            .iter()
+            .find(|file| file.mime != "dir" && file.name.to_lowercase() == *readme)
+            .map(|file| file.name.as_str())
+    })
+}
+
+/// Renders a README as HTML. Markdown goes through the same renderer as the READMEs on crate
+/// pages, which leaves out raw HTML, anything else is shown as preformatted text.
+pub(super) fn render_readme(name: &str, content: &str) -> String {
+    let lowercase = name.to_lowercase();
+    if lowercase.ends_with(".md") || lowercase.ends_with(".markdown") {
+        render_markdown(content)
+    } else {
+        format!("<pre>{}</pre>", tera::escape_html(content))
+    }
+}
+
+/// Exports the entries of a directory listing as CSV, with the columns `name`, `type` (the mime
+/// type, or `dir`) and `size`, which is left empty if it isn't known.
+pub(super) fn listing_csv(files: &[File]) -> Result<Vec<u8>> {
+    let mut csv = csv::Writer::from_writer(Vec::new());
+    csv.write_record(&["name", "type", "size"])?;
+    for file in files {
+        let size = file.size.map(|size| size.to_string()).unwrap_or_default();
+        csv.write_record(&[file.name.as_str(), file.mime.as_str(), size.as_str()])?;
+    }
+    csv.into_inner().map_err(|err| err.into_error().into())
+}
+
+/// Returns the directories of nested crates in the source of a release, recognized by their own
+/// `Cargo.toml`
+pub(super) fn workspace_members(files: &Value) -> Vec<String> {
+    let mut members: Vec<String> = files
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|file| file.get(1).and_then(Value::as_str))
+        .filter_map(|path| path.strip_suffix("/Cargo.toml"))
+        .map(|dir| dir.to_owned())
+        .collect();
+    members.sort();
+    members.dedup();
+    members
+}
+
+/// Counts the files in the source of a release by the language they're written in, the most used
+/// language first. Files of no known language aren't counted.
+pub(super) fn language_stats(files: &Value) -> Vec<(String, usize)> {
+    let mut counts = BTreeMap::new();
+    for path in files
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|file| file.get(1).and_then(Value::as_str))
+    {
+        if let Some(language) = language_for_path(path) {
+            *counts.entry(language).or_insert(0) += 1;
+        }
+    }
+
+    let mut stats: Vec<_> = counts
+        .into_iter()
+        .map(|(language, count)| (language.to_owned(), count))
+        .collect();
+    // the map is ordered by name, which the stable sort keeps for languages with the same count
+    stats.sort_by(|a, b| b.1.cmp(&a.1));
+    stats
+}
+
+/// The shape of the directory tree of a release
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct TreeShape {
+    /// The most directories a file is nested in
+    max_depth: usize,
+    /// The path of the directory with the most entries, empty for the crate root
+    widest_directory: String,
+    /// The number of entries of `widest_directory`
+    widest_directory_entries: usize,
+}
+
+/// Measures the directory tree of the source of a release, see `TreeShape`. Returns `None` if the
+/// release has no files.
+pub(super) fn tree_shape(files: &Value) -> Option<TreeShape> {
+    let mut max_depth = 0;
+    let mut entries: BTreeMap<&str, HashSet<&str>> = BTreeMap::new();
+    for path in files
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|file| file.get(1).and_then(Value::as_str))
+    {
+        max_depth = max_depth.max(path.matches('/').count());
+        // add each component of the path to the entries of the directory containing it
+        let mut parent = "";
+        let mut start = 0;
+        let ends = path.match_indices('/').map(|(idx, _)| idx);
+        for end in ends.chain(std::iter::once(path.len())) {
+            entries.entry(parent).or_default().insert(&path[start..end]);
+            parent = &path[..end];
+            start = end + 1;
+        }
+    }
+
+    // the map is ordered by path, so ties go to the first directory
+    let (widest_directory, widest_entries) = entries
+        .iter()
+        .rev()
+        .max_by_key(|(_, entries)| entries.len())?;
+    Some(TreeShape {
+        max_depth,
+        widest_directory: widest_directory.to_string(),
+        widest_directory_entries: widest_entries.len(),
+    })
+}
+
+/// Query parameters describing the view of a directory listing, which are carried over to the
+/// links of the listing so navigating back lands on the same view
+const LISTING_PARAMS: &[&str] = &[
+    "page",
+    "sort",
+    "dir",
+    "dirs-only",
+    "hidden",
+    "changed-since",
+    "group",
+];
+
+/// Builds the query string (including the leading `?`) of the listing parameters of a request,
+/// or an empty string if none were given
+pub(super) fn listing_query(req: &Request) -> String {
+    let url = req.url.as_ref();
+    let pairs = url
+        .query_pairs()
+        .filter(|(name, _)| LISTING_PARAMS.contains(&&**name))
+        .collect::<Vec<_>>();
+    if pairs.is_empty() {
+        return String::new();
+    }
+
+    let query = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs)
+        .finish();
+    format!("?{}", query)
+}
+
+/// Like `listing_query`, for the links to subdirectories. Their listings start on the first page,
+/// since the page of the listed directory has nothing to do with them.
+pub(super) fn subdirectory_query(req: &Request) -> String {
+    listing_query_with(req, &[("page", None)])
+}
+
+/// Like `listing_query`, but with the parameters in `replaced` set to the given values, or left
+/// out if the value is `None`
+fn listing_query_with(req: &Request, replaced: &[(&str, Option<&str>)]) -> String {
+    let url = req.url.as_ref();
+    let mut query = form_urlencoded::Serializer::new(String::new());
+    query.extend_pairs(url.query_pairs().filter(|(name, _)| {
+        LISTING_PARAMS.contains(&&**name) && !replaced.iter().any(|(replaced, _)| replaced == name)
+    }));
+    for (name, value) in replaced {
+        if let Some(value) = value {
+            query.append_pair(name, value);
+        }
+    }
+
+    let query = query.finish();
+    if query.is_empty() {
+        query
+    } else {
+        format!("?{}", query)
+    }
+}
+
+/// A link switching the order of a directory listing, see `sort_links`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct SortLink {
+    title: &'static str,
+    /// The query string of the link
+    href: String,
+    active: bool,
+}
+
+/// Links to sort the listing by name, type and size. The link of the active sort switches its
+/// direction.
+pub(super) fn sort_links(req: &Request, order: ListingOrder) -> Vec<SortLink> {
+    [
+        ("Name", ListingSort::Name, None),
+        ("Type", ListingSort::Type, Some("type")),
+        ("Size", ListingSort::Size, Some("size")),
+    ]
+    .iter()
+    .map(|&(title, sort, param)| {
+        let active = order.sort == sort;
+        let dir = Some("desc").filter(|_| active && !order.descending);
+        SortLink {
+            title,
+            href: listing_query_with(req, &[("sort", param), ("dir", dir)]),
+            active,
+        }
+    })
+    .collect()
+}
+
+/// The links to the neighbouring pages of a directory listing, see `LISTING_PAGE_SIZE`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct Pagination {
+    page: usize,
+    pages: usize,
+    /// The query strings of the previous and the next page, keeping the listing parameters
+    previous: Option<String>,
+    next: Option<String>,
+}
+
+impl Pagination {
+    /// Describes the position of `page` in a listing of `total` entries, or returns `None` if the
+    /// listing fits on a single page
+    pub(super) fn new(req: &Request, page: usize, total: usize) -> Option<Self> {
+        let pages = (total + LISTING_PAGE_SIZE - 1) / LISTING_PAGE_SIZE;
+        if pages <= 1 {
+            return None;
+        }
+
+        let link = |page: usize| {
+            let number = page.to_string();
+            listing_query_with(req, &[("page", Some(&*number).filter(|_| page > 1))])
+        };
+        Some(Pagination {
+            page,
+            pages,
+            previous: Some(page - 1).filter(|&page| page > 0).map(link),
+            next: Some(page + 1).filter(|&page| page <= pages).map(link),
+        })
+    }
+}
+
+/// The files before and after `name` in a directory listing, skipping the directories in it
+pub(super) fn file_neighbors<'a>(
+    files: &'a [File],
+    name: &str,
+) -> (Option<&'a str>, Option<&'a str>) {
+    let files: Vec<&str> = files
+        .iter()
+        .filter(|file| file.mime != "dir")
+        .map(|file| file.name.as_str())
+        .collect();
+    match files.iter().position(|file| *file == name) {
+        Some(index) => (
+            index.checked_sub(1).map(|index| files[index]),
+            files.get(index + 1).copied(),
+        ),
+        None => (None, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::*;
+    use test_case::test_case;
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn cargo_ok_not_skipped(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file(".cargo-ok", b"ok")
+                .source_file("README.md", b"hello")
+                .create()?;
+            let web = env.frontend();
+            assert_success("/crate/fake/0.1.0/source/", web)?;
+            Ok(())
+        });
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn dirs_only_omits_files(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("README.md", b"hello")
+                .source_file("src/lib.rs", b"mod net;")
+                .source_file("src/net/tcp.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web.get("/crate/fake/0.1.0/source/?dirs-only=1").send()?;
+            assert!(page.status().is_success());
+            let page = page.text()?;
+            assert!(page.contains(r#"href="./src/""#));
+            assert!(!page.contains("README.md"));
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/?dirs-only=1")
+                .send()?;
+            assert!(page.status().is_success());
+            let page = page.text()?;
+            assert!(page.contains(r#"href="./net/""#));
+            assert!(!page.contains("lib.rs"));
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn directory_with_only_generated_files_is_empty(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file(".cargo-ok", b"ok")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/").send()?;
+            assert!(response.status().is_success());
+            let page = response.text()?;
+            assert!(page.contains("This directory is empty"));
+            assert!(!page.contains(".cargo-ok"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn file_categories() {
+        for &(name, mime, category) in &[
+            ("lib.rs", "text/rust", super::FileCategory::Source),
+            ("build.RS", "text/plain", super::FileCategory::Source),
+            ("wrapper.h", "text/x-c", super::FileCategory::Source),
+            ("Cargo.toml", "text/toml", super::FileCategory::Config),
+            ("Cargo.lock", "text/plain", super::FileCategory::Config),
+            (".gitignore", "text/plain", super::FileCategory::Config),
+            ("README.md", "text/markdown", super::FileCategory::Doc),
+            ("LICENSE-MIT", "text/plain", super::FileCategory::Doc),
+            ("logo.png", "image/png", super::FileCategory::Image),
+            ("icon", "image/x-icon", super::FileCategory::Image),
+            ("NOTES", "text/plain", super::FileCategory::Text),
+            (
+                "data.bin",
+                "application/octet-stream",
+                super::FileCategory::Binary,
+            ),
+            ("src", "dir", super::FileCategory::Directory),
+            ("assets.png", "dir", super::FileCategory::Directory),
+        ] {
+            assert_eq!(super::file_category(name, mime), category, "{}", name);
+        }
+    }
+
+    #[test]
+    fn listing_has_file_categories() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("Cargo.toml", b"[package]")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("logo.png", b"\x89PNG\r\n\x1a\n")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            for (name, category) in &[
+                ("src/", "directory"),
+                ("Cargo.toml", "config"),
+                ("logo.png", "image"),
+            ] {
+                assert!(
+                    page.contains(&format!(
+                        r#"<a href="./{}" class="pure-menu-link file-{}">"#,
+                        name, category
+                    )),
+                    "{}",
+                    name
+                );
+            }
+
+            let list = web
+                .get("/crate/fake/0.1.0/source/src/?format=json")
+                .send()?
+                .json::<serde_json::Value>()?;
+            assert_eq!(list["files"][0]["category"], "source");
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn line_count_in_file_list() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn a() {}\nfn b() {}\nfn c() {}\n")
+                .source_file("src/main.rs", b"fn main() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains("3 lines, 30 B"), "{}", page);
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/main.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains("1 line, 12 B"));
+            let page = web.get("/crate/fake/0.1.0/source/src/").send()?.text()?;
+            assert!(!page.contains(r#"class="file-metrics""#));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn file_neighbors() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/a.rs", b"")
+                .source_file("src/b.rs", b"")
+                .source_file("src/c d.rs", b"")
+                .source_file("src/nested/mod.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            // the directory is listed first but skipped, only files are neighbors
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/b.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"<a href="./a.rs" id="prev-file""#));
+            assert!(page.contains(r#"<a href="./c%20d.rs" id="next-file""#));
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/a.rs")
+                .send()?
+                .text()?;
+            assert!(!page.contains(r#"id="prev-file""#));
+            assert!(page.contains(r#"<a href="./b.rs" id="next-file""#));
+
+            // the neighbors follow the order of the listing, and keep its parameters
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/c%20d.rs?sort=name&dir=desc")
+                .send()?
+                .text()?;
+            assert!(!page.contains(r#"id="prev-file""#));
+            assert!(page.contains(r#"<a href="./b.rs?sort=name&amp;dir=desc" id="next-file""#));
+
+            let page = web.get("/crate/fake/0.1.0/source/src/").send()?.text()?;
+            assert!(!page.contains(r#"id="next-file""#));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn conventional_root_order() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("benches/bench.rs", b"")
+                .source_file("tests/test.rs", b"")
+                .source_file("examples/example.rs", b"")
+                .source_file("aaa/file.rs", b"")
+                .source_file("src/lib.rs", b"")
+                .source_file("LICENSE", b"")
+                .source_file("Cargo.toml", b"")
+                .create()?;
+            let release =
+                super::SourceRelease::load(&mut env.db().conn(), "fake", "0.1.0")?.unwrap();
+
+            let names = |req_path: &str, sort: super::ListingSort| {
+                super::FileList::from_path(&release, req_path, false, false, sort.into(), None)
+                    .unwrap()
+                    .files
+                    .into_iter()
+                    .map(|file| file.name)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                names("", super::ListingSort::Convention),
+                [
+                    "Cargo.toml",
+                    "src",
+                    "examples",
+                    "tests",
+                    "benches",
+                    "aaa",
+                    "LICENSE"
+                ],
+            );
+            assert_eq!(
+                names("", super::ListingSort::Name),
+                [
+                    "aaa",
+                    "benches",
+                    "examples",
+                    "src",
+                    "tests",
+                    "Cargo.toml",
+                    "LICENSE"
+                ],
+            );
+
+            let page = env
+                .frontend()
+                .get("/crate/fake/0.1.0/source/?sort=convention")
+                .send()?
+                .text()?;
+            let position = |name: &str| page.find(&format!(r#"href="./{}"#, name)).unwrap();
+            assert!(position("Cargo.toml") < position("src/"));
+            assert!(position("src/") < position("examples/"));
+            assert!(position("benches/") < position("aaa/"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn duplicate_file_paths_are_listed_once() {
+        wrapper(|env| {
+            let id = env
+                .fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            env.db().conn().execute(
+                r#"UPDATE releases
+                   SET files = (files::jsonb || '[["text/plain", "src/lib.rs"]]'::jsonb)::json
+                   WHERE id = $1"#,
+                &[&id],
+            )?;
+
+            let page = env
+                .frontend()
+                .get("/crate/fake/0.1.0/source/src/")
+                .send()?
+                .text()?;
+            assert_eq!(page.matches(r#"href="./lib.rs""#).count(), 1);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn names_differing_in_case_are_listed_in_a_stable_order() {
+        wrapper(|env| {
+            let id = env
+                .fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("readme.md", b"")
+                .source_file("README.md", b"")
+                .source_file("Readme.md", b"")
+                .source_file("src/lib.rs", b"")
+                .source_file("Src/lib.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let listing = || -> Result<Vec<String>, anyhow::Error> {
+                let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+                Ok(page
+                    .match_indices(r#"<span class="text">"#)
+                    .map(|(idx, tag)| {
+                        let name = &page[idx + tag.len()..];
+                        name[..name.find('<').unwrap()].to_owned()
+                    })
+                    .filter(|name| {
+                        name.to_lowercase().contains("readme") || name.to_lowercase() == "src"
+                    })
+                    .collect())
+            };
+            let expected = vec!["Src", "src", "README.md", "Readme.md", "readme.md"];
+            assert_eq!(listing()?, expected);
+
+            // the order doesn't depend on how the paths are stored
+            env.db().conn().execute(
+                r#"UPDATE releases
+                   SET files = (SELECT json_agg(file ORDER BY file->>1 DESC) FROM json_array_elements(files) AS file)
+                   WHERE id = $1"#,
+                &[&id],
+            )?;
+            assert_eq!(listing()?, expected);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn directory_listing_is_capped() {
+        wrapper(|env| {
+            let id = env
+                .fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            env.db().conn().execute(
+                r#"UPDATE releases
+                   SET files = (
+                       SELECT json_agg(json_build_array('text/plain', 'big/' || i || '/lib.rs'))
+                       FROM generate_series(0, $2) AS i
+                   )
+                   WHERE id = $1"#,
+                &[&id, &(super::MAX_LISTED_DIRECTORIES as i32)],
+            )?;
+
+            let release =
+                super::SourceRelease::load(&mut env.db().conn(), "fake", "0.1.0")?.unwrap();
+            let list = super::FileList::from_path(
+                &release,
+                "big/",
+                false,
+                false,
+                super::ListingSort::Name.into(),
+                None,
+            )
+            .unwrap();
+            assert!(list.truncated);
+            assert_eq!(list.files.len(), super::MAX_LISTED_DIRECTORIES);
+            // the directories are sorted by name before the last ones are left out, stored last
+            // or not
+            assert!(list.files.iter().any(|file| file.name == "10000"));
+            assert!(list.files.iter().all(|file| file.name != "9999"));
+
+            let page = env
+                .frontend()
+                .get("/crate/fake/0.1.0/source/big/")
+                .send()?
+                .text()?;
+            assert!(page.contains(&format!(
+                "Too many entries, showing the first {} directories",
+                super::MAX_LISTED_DIRECTORIES
+            )));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn listing_shows_sizes() {
+        wrapper(|env| {
+            let id = env
+                .fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("README.md", &[b'a'; 2048])
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/bin/main.rs", b"fn main() {}")
+                .source_file("tests/a.rs", b"")
+                .create()?;
+            // releases from before sizes were stored only have mime types and paths
+            env.db().conn().execute(
+                r#"UPDATE releases
+                   SET files = (files::jsonb || '[["text/rust", "tests/b.rs"]]'::jsonb)::json
+                   WHERE id = $1"#,
+                &[&id],
+            )?;
+
+            let release =
+                super::SourceRelease::load(&mut env.db().conn(), "fake", "0.1.0")?.unwrap();
+            let list = |path| {
+                super::FileList::from_path(
+                    &release,
+                    path,
+                    false,
+                    false,
+                    super::ListingSort::Name.into(),
+                    None,
+                )
+                .unwrap()
+                .files
+                .into_iter()
+                .map(|file| (file.name, file.size))
+                .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                list(""),
+                vec![
+                    ("src".into(), Some(23)),
+                    ("tests".into(), None),
+                    ("README.md".into(), Some(2048)),
+                ]
+            );
+            assert_eq!(
+                list("tests/"),
+                vec![("a.rs".into(), Some(0)), ("b.rs".into(), None)]
+            );
+
+            let page = env
+                .frontend()
+                .get("/crate/fake/0.1.0/source/")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"<span class="file-size">2 KB</span>"#));
+            Ok(())
+        })
+    }
+
+    #[test_case("?format=json", None)]
+    #[test_case("", Some("application/json"))]
+    fn directory_listing_as_json(query: &str, accept: Option<&str>) {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("Cargo.toml", b"[package]")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+            let get = |path: &str| {
+                let request = web.get(&format!("{}{}", path, query));
+                match accept {
+                    Some(accept) => request.header("Accept", accept),
+                    None => request,
+                }
+                .send()
+            };
+
+            let response = get("/crate/fake/0.1.0/source/")?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["Content-Type"], "application/json");
+            let list = response.json::<serde_json::Value>()?;
+            assert_eq!(list["metadata"]["name"], "fake");
+            assert_eq!(list["metadata"]["version"], "0.1.0");
+            let files: Vec<_> = list["files"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|file| {
+                    (
+                        file["name"].as_str().unwrap(),
+                        file["mime"].as_str().unwrap(),
+                    )
+                })
+                .collect();
+            assert_eq!(files, vec![("src", "dir"), ("Cargo.toml", "text/toml")]);
+
+            let list = get("/crate/fake/0.1.0/source/src/")?.json::<serde_json::Value>()?;
+            assert_eq!(list["files"][0]["name"], "lib.rs");
+
+            let response = get("/crate/fake/0.1.0/source/missing/")?;
+            assert_eq!(response.status(), 404);
+            assert_eq!(response.headers()["Content-Type"], "application/json");
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn directory_listing_as_csv() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/a,b \"c\".rs", b"")
+                .source_file("src/bin/main.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/src/?format=csv").send()?;
+            assert!(response.status().is_success());
+            assert_eq!(
+                response.headers()["Content-Type"],
+                "text/csv; charset=utf-8"
+            );
+            assert_eq!(
+                response.text()?,
+                "name,type,size\n\
+                 bin,dir,0\n\
+                 \"a,b \"\"c\"\".rs\",text/rust,0\n\
+                 lib.rs,text/rust,11\n"
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn listing_links_keep_the_listing_view() {
+        wrapper(|env| {
+            // enough directories to push `net/` and `lib.rs` to the second page of `src/`
+            let fillers: Vec<String> = (0..100).map(|i| format!("src/d{:03}/x.rs", i)).collect();
+            let mut release = env
+                .fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/net/mod.rs", b"");
+            for path in &fillers {
+                release = release.source_file(path, b"");
+            }
+            release.create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/?page=2&lang=rust")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"href="./lib.rs?page=2""#));
+            assert!(page.contains(r#"href="../?page=2""#));
+            // a subdirectory is listed from its first page
+            assert!(page.contains(r#"href="./net/""#));
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/?page=2&sort=convention")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"href="./lib.rs?page=2&sort=convention""#));
+            assert!(page.contains(r#"href="./net/?sort=convention""#));
+
+            let page = web.get("/crate/fake/0.1.0/source/src/").send()?.text()?;
+            assert!(page.contains(r#"href="./lib.rs""#));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn workspace_members() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("Cargo.toml", b"")
+                .source_file("members/a/Cargo.toml", b"")
+                .source_file("members/a/src/lib.rs", b"")
+                .source_file("members/b/Cargo.toml", b"")
+                .source_file("members/c#/Cargo.toml", b"")
+                .source_file("src/lib.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            for member in &["members/a", "members/b", "members/c%23"] {
+                assert!(page.contains(&format!(r#"href="/crate/fake/0.1.0/source/{}/""#, member)));
+            }
+            assert!(!page.contains(r#"href="/crate/fake/0.1.0/source//""#));
+
+            assert_success("/crate/fake/0.1.0/source/members/a/", web)?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn language_stats() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("Cargo.toml", b"")
+                .source_file("src/lib.rs", b"")
+                .source_file("src/main.rs", b"")
+                .source_file("src/ffi.rs", b"")
+                .source_file("native/foo.c", b"")
+                .source_file("native/bar.c", b"")
+                .source_file("LICENSE", b"")
+                .create()?;
+            let release =
+                super::SourceRelease::load(&mut env.db().conn(), "fake", "0.1.0")?.unwrap();
+            assert_eq!(
+                super::language_stats(release.file_list()),
+                vec![
+                    ("rust".to_owned(), 3),
+                    ("c".to_owned(), 2),
+                    ("toml".to_owned(), 1),
+                ],
+            );
+
+            let web = env.frontend();
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            assert!(page.contains(r#"<span class="text">rust</span> <span class="count">3</span>"#));
+            let page = web.get("/crate/fake/0.1.0/source/src/").send()?.text()?;
+            assert!(!page.contains("language-stat"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn language_stats_are_cached() {
+        wrapper(|env| {
+            env.override_config(|config| config.source_release_cache_capacity = 10);
+            let id = env
+                .fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"")
+                .source_file("src/main.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let stat = r#"<span class="text">rust</span> <span class="count">2</span>"#;
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            assert!(page.contains(stat));
+
+            // the file list of a release never changes, so the cached stats are still served
+            env.db().conn().execute(
+                r#"UPDATE releases SET files = '[["text/rust", "src/lib.rs"]]' WHERE id = $1"#,
+                &[&id],
+            )?;
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            assert!(page.contains(stat));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn tree_shape() {
+        let files = serde_json::json!([
+            ["text/toml", "Cargo.toml"],
+            ["text/rust", "src/lib.rs"],
+            ["text/rust", "src/a.rs"],
+            ["text/rust", "src/net/tcp/mod.rs"],
+            ["text/rust", "src/net/udp.rs"],
+            ["text/rust", "tests/a.rs"],
+            ["text/rust", "tests/b.rs"],
+            ["text/rust", "tests/c.rs"],
+            ["text/rust", "tests/d/e.rs"],
+        ]);
+        assert_eq!(
+            super::tree_shape(&files),
+            Some(super::TreeShape {
+                max_depth: 3,
+                widest_directory: "src".into(),
+                widest_directory_entries: 3,
+            })
+        );
+        // the first directory wins a tie
+        assert_eq!(
+            super::tree_shape(&serde_json::json!([
+                ["text/rust", "a/b/1.rs"],
+                ["text/rust", "a/b/2.rs"],
+                ["text/rust", "a/c/1.rs"],
+                ["text/rust", "a/c/2.rs"],
+            ]))
+            .map(|shape| shape.widest_directory),
+            Some("a".into())
+        );
+        assert_eq!(super::tree_shape(&serde_json::json!([])), None);
+
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"")
+                .source_file("src/a/b/c.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            assert!(page.contains("Deepest nesting: 3 directories"));
+            assert!(page.contains("Widest directory: src/ (2 entries)"));
+            let page = web.get("/crate/fake/0.1.0/source/src/").send()?.text()?;
+            assert!(!page.contains("Deepest nesting"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn tree_shape_is_cached() {
+        wrapper(|env| {
+            env.override_config(|config| config.source_release_cache_capacity = 10);
+            let id = env
+                .fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/a/b/c.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            assert!(page.contains("Deepest nesting: 3 directories"));
+
+            // the file list of a release never changes, so the cached shape is still served
+            env.db().conn().execute(
+                r#"UPDATE releases SET files = '[["text/rust", "src/lib.rs"]]' WHERE id = $1"#,
+                &[&id],
+            )?;
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            assert!(page.contains("Deepest nesting: 3 directories"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn large_listings_are_grouped() {
+        wrapper(|env| {
+            let paths: Vec<String> = (0..20)
+                .map(|i| format!("dir{}/lib.rs", i))
+                .chain((0..25).map(|i| format!("file{}.rs", i)))
+                .chain((0..10).map(|i| format!("file{}.md", i)))
+                .collect();
+            let mut release = env.fake_release().name("fake").version("0.1.0");
+            for path in &paths {
+                release = release.source_file(path, b"");
+            }
+            release.create()?;
+            let web = env.frontend();
+
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            for (title, count) in &[("Directories", 20), ("Rust files", 25), ("Other files", 10)] {
+                assert!(page.contains(&format!(
+                    r#"<summary class="pure-menu-heading">{} <span class="count">{}</span></summary>"#,
+                    title, count
+                )));
+            }
+            assert_eq!(page.matches(r#"<span class="text">file"#).count(), 35);
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/?group=0")
+                .send()?
+                .text()?;
+            assert!(!page.contains("<summary"));
+            assert_eq!(page.matches(r#"<span class="text">file"#).count(), 35);
+            // the flat listing is kept when browsing on
+            assert!(page.contains(r#"href="./dir0/?group=0""#));
+
+            // small listings aren't grouped
+            let page = web.get("/crate/fake/0.1.0/source/dir0/").send()?.text()?;
+            assert!(!page.contains("<summary"));
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn directory_readme(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .archive_storage(archive_storage)
+                .source_file("README", b"plain <b>text</b>")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/README.md", b"# Hello\n\n<script>alert(1)</script>\n")
+                .source_file("src/readme.txt", b"not shown")
+                .source_file("tests/lib.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web.get("/crate/fake/0.1.0/source/src/").send()?.text()?;
+            assert!(page.contains("<h1>Hello</h1>"));
+            assert!(!page.contains("<script>alert(1)</script>"));
+            assert!(!page.contains("not shown"));
+
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            assert!(page.contains("<pre>plain &lt;b&gt;text&lt;&#x2F;b&gt;</pre>"));
+
+            let page = web.get("/crate/fake/0.1.0/source/tests/").send()?.text()?;
+            assert!(!page.contains("directory-readme"));
+
+            // the README isn't shown next to files
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(!page.contains("<h1>Hello</h1>"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn large_listings_are_paginated() {
+        wrapper(|env| {
+            let paths: Vec<String> = (0..140)
+                .map(|i| format!("file{:03}.rs", i))
+                .chain((0..10).map(|i| format!("dir{}/lib.rs", i)))
+                .collect();
+            let mut release = env.fake_release().name("fake").version("0.1.0");
+            for path in &paths {
+                release = release.source_file(path, b"");
+            }
+            release.create()?;
+            let web = env.frontend();
+
+            let names = |query: &str| -> Result<Vec<String>, anyhow::Error> {
+                let listing: serde_json::Value = web
+                    .get(&format!("/crate/fake/0.1.0/source/?format=json{}", query))
+                    .send()?
+                    .json()?;
+                assert_eq!(listing["total"], 150);
+                Ok(listing["files"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|file| file["name"].as_str().unwrap().to_owned())
+                    .collect())
+            };
+            let first = names("")?;
+            let second = names("&page=2")?;
+            assert_eq!(first, names("&page=1")?);
+            assert_eq!(first.len(), 100);
+            assert_eq!(second.len(), 50);
+            // directories come first on the first page, and no entry is on both pages
+            assert_eq!(first[0], "dir0");
+            assert!(first.iter().all(|name| !second.contains(name)));
+            assert_eq!(second.last().unwrap(), "file139.rs");
+
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            assert!(page.contains("Page 1 of 2"));
+            assert!(page.contains(r#"href="./?page=2""#));
+            let page = web
+                .get("/crate/fake/0.1.0/source/?page=2&sort=convention")
+                .send()?
+                .text()?;
+            assert!(page.contains("Page 2 of 2"));
+            assert!(page.contains(r#"href="./?sort=convention""#));
+            assert!(!page.contains("next-page"));
+
+            assert_eq!(
+                web.get("/crate/fake/0.1.0/source/?page=3").send()?.status(),
+                404
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn listing_sort_modes() {
+        wrapper(|env| {
+            let id = env
+                .fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("a.rs", &[b'a'; 30])
+                .source_file("B.md", &[b'b'; 10])
+                .source_file("c.toml", &[b'c'; 20])
+                .source_file("d/x.rs", &[b'd'; 5])
+                .source_file("e/y.rs", &[b'e'; 50])
+                .create()?;
+            let web = env.frontend();
+
+            let names = |query: &str| -> Result<Vec<String>, anyhow::Error> {
+                let listing: serde_json::Value = web
+                    .get(&format!("/crate/fake/0.1.0/source/?format=json&{}", query))
+                    .send()?
+                    .json()?;
+                Ok(listing["files"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|file| file["name"].as_str().unwrap().to_owned())
+                    .collect())
+            };
+            assert_eq!(names("")?, ["d", "e", "a.rs", "B.md", "c.toml"]);
+            assert_eq!(names("sort=name")?, ["d", "e", "a.rs", "B.md", "c.toml"]);
+            assert_eq!(
+                names("sort=name&dir=desc")?,
+                ["e", "d", "c.toml", "B.md", "a.rs"]
+            );
+            assert_eq!(names("sort=type")?, ["d", "e", "B.md", "a.rs", "c.toml"]);
+            assert_eq!(names("sort=size")?, ["d", "e", "B.md", "c.toml", "a.rs"]);
+            assert_eq!(
+                names("sort=size&dir=desc")?,
+                ["e", "d", "a.rs", "c.toml", "B.md"]
+            );
+
+            // the active sort is marked, and its link switches the direction
+            let page = web
+                .get("/crate/fake/0.1.0/source/?sort=size&dir=desc")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"<a href="./?sort=size" class="sort-link active">"#));
+            assert!(page.contains(r#"<a href="./?sort=type" class="sort-link">"#));
+            assert!(page.contains(r#"<a href="./" class="sort-link">"#));
+
+            // releases from before sizes were stored are sorted by name
+            env.db().conn().execute(
+                r#"UPDATE releases
+                   SET files = (SELECT json_agg(json_build_array(file->0, file->1)) FROM json_array_elements(files) AS file)
+                   WHERE id = $1"#,
+                &[&id],
+            )?;
+            assert_eq!(names("sort=size")?, ["d", "e", "a.rs", "B.md", "c.toml"]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn hidden_files() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file(".gitignore", b"target")
+                .source_file(".github/workflows/ci.yml", b"")
+                .source_file("conf/.env", b"")
+                .source_file("src/lib.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let names = |path: &str| -> Result<Vec<String>, anyhow::Error> {
+                let response = web.get(path).send()?;
+                assert!(response.status().is_success());
+                let listing: serde_json::Value = response.json()?;
+                Ok(listing["files"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|file| file["name"].as_str().unwrap().to_owned())
+                    .collect())
+            };
+            assert_eq!(
+                names("/crate/fake/0.1.0/source/?format=json")?,
+                ["conf", "src"]
+            );
+            assert_eq!(
+                names("/crate/fake/0.1.0/source/?format=json&hidden=true")?,
+                [".github", "conf", "src", ".gitignore"]
+            );
+
+            // a directory containing only hidden files still exists, it's just empty
+            assert!(names("/crate/fake/0.1.0/source/conf/?format=json")?.is_empty());
+            assert_eq!(
+                names("/crate/fake/0.1.0/source/conf/?format=json&hidden=true")?,
+                [".env"]
+            );
+
+            // the choice is carried over to the links of the listing
+            let page = web
+                .get("/crate/fake/0.1.0/source/?hidden=true")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"href="./.github/?hidden=true""#));
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn minimal_crate(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("Cargo.toml", b"[package]")
+                .source_file("src/main.rs", b"fn main() {}")
+                .create()?;
+
+            let release =
+                super::SourceRelease::load(&mut env.db().conn(), "fake", "0.1.0")?.unwrap();
+            let root = super::FileList::from_path(
+                &release,
+                "",
+                false,
+                false,
+                super::ListingSort::Name.into(),
+                None,
+            )
+            .unwrap();
+            let files: Vec<_> = root
+                .files
+                .iter()
+                .map(|file| (file.name.as_str(), file.mime.as_str()))
+                .collect();
+            assert_eq!(files, [("src", "dir"), ("Cargo.toml", "text/toml")]);
+
+            let web = env.frontend();
+            let page = web.get("/crate/fake/0.1.0/source/").send()?.text()?;
+            assert!(page.contains(r#"href="./src/""#));
+            assert!(page.contains(r#"href="./Cargo.toml""#));
+            // there's no parent of the crate root
+            assert!(!page.contains(r#"href="../""#));
+
+            let page = web.get("/crate/fake/0.1.0/source/src/").send()?.text()?;
+            assert!(page.contains(r#"href="./main.rs""#));
+            assert!(page.contains(r#"href="../""#));
+            Ok(())
+        })
+    }
+}
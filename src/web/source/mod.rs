@@ -0,0 +1,4218 @@
+//! Source code browser
+
+mod cache;
+mod content;
+mod diff;
+mod download;
+mod listing;
+mod search;
+mod tree;
+
+pub use self::cache::{source_warm_handler, SourceCaches};
+pub use self::diff::{file_versions_handler, source_diff_handler};
+pub use self::download::{
+    source_by_hash_handler, source_checksums_handler, source_files_handler, source_tarball_handler,
+};
+pub use self::tree::source_tree_handler;
+use self::{
+    content::{
+        cargo_toml_deps, check_syntax, docs_link, has_extension, hexdump, image_dimensions,
+        is_hexdump_previewed, is_text_file, known_language, language_for_path, line_count,
+        looks_like_text, mime_disagrees_with_extension, public_module_paths, rendering_allowed,
+        served_mime, source_lines, strip_bom, transcode, truncate_content, SourceLine, SyntaxCheck,
+        PREVIEWED_IMAGE_TYPES,
+    },
+    diff::{entry_change, ReleaseSource, MAX_CHANGE_COMPARISONS},
+    download::{directory_zip, MAX_DIRECTORY_DOWNLOAD_SIZE},
+    listing::{
+        directory_readme_name, file_neighbors, language_stats, listing_csv, listing_groups,
+        listing_query, render_readme, sort_links, subdirectory_query, tree_shape,
+        workspace_members, FileList, ListingGroup, ListingOrder, ListingPage, Pagination, SortLink,
+        TreeShape, GROUPED_LISTING_SIZE, MAX_LISTED_DIRECTORIES,
+    },
+    search::search_files,
+};
+use crate::{
+    db::Pool,
+    error::Result,
+    impl_webpage,
+    storage::Blob,
+    web::{
+        error::Nope,
+        file::File as DbFile,
+        highlight::{highlight, Highlighted},
+        match_version,
+        page::WebPage,
+        redirect_base, ErrorPage, MatchSemver, MetaData, Url,
+    },
+    Config, Storage,
+};
+use flate2::{write::GzEncoder, Compression};
+use iron::{
+    headers::{
+        AcceptEncoding, AcceptRanges, CacheControl, CacheDirective, ContentEncoding, ContentLength,
+        ContentRange, ContentRangeSpec, ContentType, ETag, Encoding, EntityTag, IfNoneMatch,
+        Quality, RangeUnit,
+    },
+    modifiers::Redirect,
+    status,
+    url::{
+        form_urlencoded,
+        percent_encoding::{percent_decode, utf8_percent_encode, PATH_SEGMENT_ENCODE_SET},
+    },
+    IronResult, Request, Response,
+};
+use postgres::Client;
+use router::Router;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    borrow::Cow,
+    time::{Duration, Instant},
+};
+
+/// Exposes how a source file was classified so clients can tell without parsing the page
+fn set_file_type_headers(response: &mut Response, is_text: bool, language: Option<&str>) {
+    response
+        .headers
+        .set_raw("X-Docsrs-Is-Text", vec![is_text.to_string().into_bytes()]);
+    if let Some(language) = language {
+        response
+            .headers
+            .set_raw("X-Docsrs-Language", vec![language.as_bytes().to_vec()]);
+    }
+}
+
+/// Lets caches keep a page rendered from the source of an exact release as long as they
+/// revalidate it, see `source_etag`, since e.g. yanking the release changes the page. The page is
+/// tagged with the crate name, so a CDN can purge all source pages of a crate at once.
+fn set_cache_headers(response: &mut Response, crate_name: &str) {
+    response.headers.set(CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(0),
+        CacheDirective::MustRevalidate,
+    ]));
+    set_cache_tags(response, crate_name);
+}
+
+/// Marks the stored bytes of an exact release, which never change, as immutable for caches and
+/// tags them with the crate name like `set_cache_headers` does
+fn set_immutable_cache_headers(response: &mut Response, crate_name: &str) {
+    response.headers.set(CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(super::STATIC_FILE_CACHE_DURATION as u32),
+        CacheDirective::Extension("immutable".into(), None),
+    ]));
+    set_cache_tags(response, crate_name);
+}
+
+fn set_cache_tags(response: &mut Response, crate_name: &str) {
+    for header in &["Surrogate-Key", "Cache-Tag"] {
+        response
+            .headers
+            .set_raw(*header, vec![crate_name.as_bytes().to_vec()]);
+    }
+}
+
+/// Percent-encodes a crate name or version for use as a single segment of a redirect URL.
+///
+/// `+` is encoded as well, since build metadata would otherwise be read as a space by some
+/// clients and caches.
+fn encode_path_segment(segment: &str) -> String {
+    utf8_percent_encode(segment, PATH_SEGMENT_ENCODE_SET)
+        .to_string()
+        .replace('+', "%2B")
+}
+
+/// Everything the source browser needs to know about a release, loaded once per request
+struct SourceRelease {
+    metadata: MetaData,
+    /// The stored list of all source files, missing for some old releases
+    files: Option<Value>,
+    archive_storage: bool,
+    /// The repository URL, for `Config::source_issue_link_template`
+    repository_url: Option<String>,
+}
+
+impl SourceRelease {
+    fn load(
+        conn: &mut Client,
+        name: &str,
+        version: &str,
+    ) -> Result<Option<SourceRelease>, postgres::Error> {
+        let row = conn.query_opt(
+            "SELECT crates.name,
+                    releases.version,
+                    releases.description,
+                    releases.target_name,
+                    releases.rustdoc_status,
+                    releases.files,
+                    releases.default_target,
+                    releases.doc_targets,
+                    releases.yanked,
+                    releases.archive_storage,
+                    releases.repository_url
+            FROM releases
+            INNER JOIN crates ON crates.id = releases.crate_id
+            WHERE crates.name = $1 AND releases.version = $2",
+            &[&name, &version],
+        )?;
+
+        Ok(row.map(|row| SourceRelease {
+            metadata: MetaData {
+                name: row.get(0),
+                version: row.get(1),
+                description: row.get(2),
+                target_name: row.get(3),
+                rustdoc_status: row.get(4),
+                default_target: row.get(6),
+                doc_targets: MetaData::parse_doc_targets(row.get(7)),
+                yanked: row.get(8),
+            },
+            files: row.get(5),
+            archive_storage: row.get(9),
+            repository_url: row.get(10),
+        }))
+    }
+
+    /// The file list, or `Value::Null` if the release has none, which the helpers taking the
+    /// file list treat like an empty one
+    fn file_list(&self) -> &Value {
+        self.files.as_ref().unwrap_or(&Value::Null)
+    }
+}
+
+/// What a path inside the source of a release refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum PathKind {
+    File,
+    Dir,
+    Missing,
+}
+
+/// Classifies a path relative to the crate root using the stored list of files, without touching
+/// the storage
+fn classify_path(files: &Value, path: &str) -> PathKind {
+    let path = path.trim_end_matches('/');
+    if path.is_empty() {
+        return PathKind::Dir;
+    }
+
+    let dir = format!("{}/", path);
+    let mut kind = PathKind::Missing;
+    for file in files.as_array().into_iter().flatten() {
+        match file.get(1).and_then(Value::as_str) {
+            Some(file) if file == path => return PathKind::File,
+            Some(file) if file.starts_with(&dir) => kind = PathKind::Dir,
+            _ => {}
+        }
+    }
+
+    kind
+}
+
+/// Returns the paths of the files in the directory `dir`, including the files in its
+/// subdirectories if `recursive` is set
+fn directory_file_paths(files: &Value, dir: &str, recursive: bool) -> Vec<String> {
+    files
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|file| file.get(1).and_then(Value::as_str))
+        // skip .cargo-ok generated by cargo
+        .filter(|path| *path != ".cargo-ok")
+        .filter(|path| {
+            path.strip_prefix(dir)
+                .map_or(false, |rest| recursive || !rest.contains('/'))
+        })
+        .map(|path| path.to_owned())
+        .collect()
+}
+
+/// Builds the link to the issues mentioning a file from the configured template
+fn issue_link(template: &str, repository_url: &str, path: &str) -> String {
+    let path: String = form_urlencoded::byte_serialize(path.as_bytes()).collect();
+    template
+        .replace("{repository}", repository_url.trim_end_matches('/'))
+        .replace("{path}", &path)
+}
+
+/// Whether loading a file failed because it exceeds the configured maximum file size
+fn is_size_limit_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .and_then(|io| io.get_ref())
+        .and_then(|err| err.downcast_ref::<crate::error::SizeLimitReached>())
+        .is_some()
+}
+
+/// Returns the deepest directory of the release containing `path`, or an empty string if only
+/// the crate root exists.
+fn closest_existing_directory(files: &Value, path: &str) -> String {
+    let paths: Vec<&str> = files
+        .as_array()
+        .map(|files| {
+            files
+                .iter()
+                .filter_map(|file| file.get(1).and_then(Value::as_str))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut candidate = path.trim_end_matches('/');
+    while let Some(idx) = candidate.rfind('/') {
+        candidate = &candidate[..idx];
+        let dir = format!("{}/", candidate);
+        if paths.iter().any(|path| path.starts_with(&dir)) {
+            return dir;
+        }
+    }
+
+    String::new()
+}
+
+/// Strips leading zeros from the numeric components of a version like `01.0.0`, so that slightly
+/// mistyped versions still match the version stored in the database.
+fn normalize_version(version: &str) -> Cow<'_, str> {
+    let core_end = version
+        .find(|c| c == '-' || c == '+')
+        .unwrap_or(version.len());
+    let (core, rest) = version.split_at(core_end);
+
+    let has_leading_zeros = core.split('.').any(|component| {
+        component.len() > 1
+            && component.starts_with('0')
+            && component.bytes().all(|b| b.is_ascii_digit())
+    });
+    if !has_leading_zeros {
+        return Cow::Borrowed(version);
+    }
+
+    let core = core
+        .split('.')
+        .map(|component| {
+            if component.bytes().all(|b| b.is_ascii_digit()) {
+                match component.trim_start_matches('0') {
+                    "" => "0",
+                    trimmed => trimmed,
+                }
+            } else {
+                component
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".");
+
+    Cow::Owned(core + rest)
+}
+
+/// Gets the value of the query parameter `key`, if it was given
+fn query_param(req: &Request, key: &str) -> Option<String> {
+    req.url
+        .as_ref()
+        .query_pairs()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Whether the query parameter `key` was set to a truthy value like `1` or `true`
+fn query_flag(req: &Request, key: &str) -> bool {
+    query_param(req, key).map_or(false, |value| value == "1" || value == "true")
+}
+
+/// Why a requested source path can't be inside the release, if it can't. The path is checked
+/// after percent-decoding it, so an encoded `..%2F..` is caught just like a literal one, and it's
+/// rejected before it reaches the storage backend.
+fn invalid_source_path(path: &str) -> Option<&'static str> {
+    if path.contains('\0') {
+        Some("the requested path contains a null byte")
+    } else if path.starts_with('/') {
+        Some("the requested path must be relative to the release")
+    } else if path
+        .split(|c| c == '/' || c == '\\')
+        .any(|part| part == "..")
+    {
+        Some("the requested path may not contain `..`")
+    } else {
+        None
+    }
+}
+
+/// Whether the client asked for a machine-readable response instead of an HTML page, with
+/// `?format=json` or an `Accept` header
+fn wants_json(req: &Request) -> bool {
+    if query_param(req, "format").as_deref() == Some("json") {
+        return true;
+    }
+    req.headers
+        .get_raw("Accept")
+        .and_then(|values| values.get(0))
+        .and_then(|value| std::str::from_utf8(value).ok())
+        .map_or(false, |accept| accept.contains("application/json"))
+}
+
+/// Builds the JSON 404 returned to API clients when the crate and version exist but the
+/// requested path doesn't
+fn path_not_found_json(files: &Value, name: &str, version: &str, path: &str) -> Response {
+    let body = json!({
+        "error": "path_not_found",
+        "crate": name,
+        "version": version,
+        "path": path,
+        "closest_directory": closest_existing_directory(files, path),
+    });
+
+    let mut resp = Response::with((status::NotFound, body.to_string()));
+    resp.headers.set(ContentType::json());
+    resp
+}
+
+/// Builds the JSON returned to API clients for a single file. The content of a text file is
+/// included, a binary file only comes with a link to download it.
+///
+/// `fields` is the comma-separated list of fields given with `?fields=`, e.g. `path,size`. Only
+/// these fields are returned then, unknown ones are ignored, so clients can leave out the
+/// potentially large content.
+fn file_json(
+    path: &str,
+    mime: &str,
+    is_rust_source: bool,
+    size: usize,
+    content: Option<String>,
+    download_url: String,
+    fields: Option<&str>,
+) -> Response {
+    let line_count = content.as_deref().map(line_count);
+    let body = match content {
+        Some(content) => json!({
+            "path": path,
+            "mime": mime,
+            "is_rust_source": is_rust_source,
+            "size": size,
+            "line_count": line_count,
+            "content": content,
+        }),
+        None => json!({
+            "path": path,
+            "mime": mime,
+            "is_rust_source": is_rust_source,
+            "size": size,
+            "line_count": null,
+            "content": null,
+            "download_url": download_url,
+        }),
+    };
+    let body = match (body, fields) {
+        (Value::Object(body), Some(fields)) => {
+            let fields: Vec<&str> = fields.split(',').map(str::trim).collect();
+            Value::Object(
+                body.into_iter()
+                    .filter(|(field, _)| fields.contains(&field.as_str()))
+                    .collect(),
+            )
+        }
+        (body, _) => body,
+    };
+
+    let mut resp = Response::with((status::Ok, body.to_string()));
+    resp.headers.set(ContentType::json());
+    resp
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct SourcePage {
+    file_list: FileList,
+    show_parent_link: bool,
+    file_content: Option<String>,
+    /// `file_content` as highlighted HTML, see `Config::source_server_highlighting`
+    highlighted_content: Option<String>,
+    /// Whether highlighting `file_content` on the server took too long, so it's shown as plain
+    /// text, see `highlight::HIGHLIGHT_BUDGET`
+    highlight_timed_out: bool,
+    /// The lines of `file_content`, used to render a linkable line number for each
+    lines: Vec<SourceLine>,
+    /// The number of lines and the size in bytes of the shown file, see `line_count`
+    line_count: Option<usize>,
+    byte_size: Option<usize>,
+    /// Whether `file_content` was cut off, see `truncate_content`
+    truncated: bool,
+    /// Whether `file_content` consists of nothing but whitespace, which would look like a
+    /// rendering bug without a note
+    whitespace_only: bool,
+    is_rust_source: bool,
+    /// The listing parameters of the request, appended to the links of the listing
+    listing_query: String,
+    /// `listing_query` for the links to subdirectories, see `subdirectory_query`
+    subdirectory_query: String,
+    /// The order of the listing and the links to change it
+    listing_order: ListingOrder,
+    sort_links: Vec<SortLink>,
+    /// The links to the other pages of the listing, if it has more than one
+    pagination: Option<Pagination>,
+    /// See `MAX_LISTED_DIRECTORIES`, for the note on truncated listings
+    max_listed_directories: usize,
+    /// The entries of `file_list` split into sections, if the listing is large enough, see
+    /// `listing_groups`
+    listing_groups: Vec<ListingGroup>,
+    /// The names and links of the directories leading to the listed one, see `breadcrumbs`
+    breadcrumbs: Vec<(String, String)>,
+    /// The link to this page in the exact release, see `permalink`
+    permalink: String,
+    /// The latest release that isn't yanked and the link to this page in it, if this release was
+    /// yanked
+    unyanked_link: Option<(String, String)>,
+    /// The percent-encoded names of the files before and after this one in its directory, see
+    /// `file_neighbors`
+    prev_file: Option<String>,
+    next_file: Option<String>,
+    /// The directories of crates nested in this one, see `workspace_members`
+    workspace_members: Vec<WorkspaceMember>,
+    /// The number of files per language at the crate root, see `language_stats`
+    language_stats: Vec<(String, usize)>,
+    /// The shape of the directory tree at the crate root, see `tree_shape`
+    tree_shape: Option<TreeShape>,
+    /// The highlighter forced with `?lang=`, instead of letting the highlighter detect it
+    forced_language: Option<&'static str>,
+    /// The stored mime type of the file, if it contradicted the file's extension
+    type_mismatch: Option<String>,
+    /// Whether the file is an executable script, which is shown as plain text with a note
+    script: bool,
+    /// The result of parsing the file, if `?check=1` was requested for a Rust file
+    syntax_check: Option<SyntaxCheck>,
+    /// A link to the issues mentioning the file, see `Config::source_issue_link_template`
+    issue_link: Option<String>,
+    /// A link to the documentation of the module the file is, see `docs_link`
+    docs_link: Option<String>,
+    /// Describes the requested file if it can't be shown, see `Config::source_placeholders`
+    placeholder: Option<Placeholder>,
+    /// The README of the listed directory rendered as HTML, see `directory_readme_name`
+    directory_readme: Option<String>,
+    /// Whether the file is a small binary file previewed as `hexdump`
+    is_binary_preview: bool,
+    hexdump: Option<String>,
+    /// The image shown instead of the file's contents, see `PREVIEWED_IMAGE_TYPES`
+    image_preview: Option<ImagePreview>,
+    /// The encoding `file_content` was converted from if it wasn't UTF-8, see `transcode`
+    detected_encoding: Option<String>,
+    /// The dependencies declared in a `Cargo.toml` and the links to them, see `cargo_toml_deps`
+    cargo_toml_deps: Option<Vec<(String, String)>>,
+}
+
+/// A crate nested in the one browsed, see `workspace_members`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct WorkspaceMember {
+    /// The directory of the crate
+    path: String,
+    /// The directory in the source browser, already percent-encoded
+    link: String,
+}
+
+/// An image file that's shown on the page
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ImagePreview {
+    name: String,
+    size: usize,
+    /// The width and height of the image, if they could be read, see `image_dimensions`
+    dimensions: Option<(u32, u32)>,
+}
+
+/// The description of a file that is shown instead of its contents
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Placeholder {
+    /// Why the file isn't shown, `binary`, `oversized`, `unsupported` or `large`
+    kind: &'static str,
+    name: String,
+    mime: Option<String>,
+    size: Option<usize>,
+    /// Whether the file can be downloaded with `?download=1`
+    downloadable: bool,
+}
+
+impl_webpage! {
+    SourcePage = "crate/source.html",
+}
+
+/// A rendered source file without the rest of the page, served for `?fragment=1`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct SourceFragment {
+    file_content: String,
+    highlighted_content: Option<String>,
+    lines: Vec<SourceLine>,
+    /// The highlight.js language of the file, so the fragment can be highlighted where it's
+    /// embedded
+    language: Option<&'static str>,
+}
+
+impl_webpage! {
+    SourceFragment = "crate/source_fragment.html",
+}
+
+/// Returns the name and the source URL of each directory leading to `req_path`, starting with the
+/// crate root, which is always included
+fn breadcrumbs(name: &str, version: &str, req_path: &str) -> Vec<(String, String)> {
+    let mut href = format!(
+        "/crate/{}/{}/source/",
+        encode_path_segment(name),
+        encode_path_segment(version)
+    );
+    let mut breadcrumbs = vec![(name.to_owned(), href.clone())];
+    for segment in req_path.split('/').filter(|segment| !segment.is_empty()) {
+        href.push_str(&encode_path_segment(segment));
+        href.push('/');
+        breadcrumbs.push((segment.to_owned(), href.clone()));
+    }
+    breadcrumbs
+}
+
+/// The source URL of `file_path` in an exact release, which keeps pointing to the same file or
+/// directory once newer releases are published
+fn permalink(name: &str, version: &str, file_path: &str) -> String {
+    let path: Vec<String> = file_path.split('/').map(encode_path_segment).collect();
+    format!(
+        "/crate/{}/{}/source/{}",
+        encode_path_segment(name),
+        encode_path_segment(version),
+        path.join("/"),
+    )
+}
+
+/// Whether a text file is too large to be rendered in a page, so it's served as it is instead,
+/// see `Config::source_render_threshold`
+fn is_too_large_to_render(config: &Config, size: usize) -> bool {
+    size > config.source_render_threshold
+}
+
+/// The part of a file requested with a `Range` header, see `byte_range`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    /// No range was requested, or one that's served as the whole file
+    Full,
+    /// The bytes from `start` to `end`, inclusive
+    Partial { start: usize, end: usize },
+    /// The byte range is outside of the file
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header like `bytes=0-499`, `bytes=500-` or `bytes=-500` for a file of `len`
+/// bytes. Requests for multiple ranges are answered with the whole file, like servers that don't
+/// support ranges do, and so are ranges in units other than bytes and malformed ranges, which
+/// RFC 7233 says to ignore.
+fn byte_range(range: Option<&str>, len: usize) -> ByteRange {
+    let spec = match range.and_then(|range| range.trim().strip_prefix("bytes=")) {
+        Some(spec) => spec,
+        None => return ByteRange::Full,
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let (start, end) = match spec.split_once('-') {
+        Some((start, end)) => (start.trim(), end.trim()),
+        None => return ByteRange::Full,
+    };
+
+    let range = match (start, end) {
+        // the last `end` bytes
+        ("", suffix) => match suffix.parse::<usize>() {
+            Ok(suffix) if suffix > 0 && len > 0 => Some((len - suffix.min(len), len - 1)),
+            Ok(_) => None,
+            Err(_) => return ByteRange::Full,
+        },
+        (start, "") => match start.parse::<usize>() {
+            Ok(start) if start < len => Some((start, len - 1)),
+            Ok(_) => None,
+            Err(_) => return ByteRange::Full,
+        },
+        (start, end) => match (start.parse::<usize>(), end.parse::<usize>()) {
+            (Ok(start), Ok(end)) if start > end => return ByteRange::Full,
+            (Ok(start), Ok(end)) if start < len => Some((start, end.min(len - 1))),
+            (Ok(_), Ok(_)) => None,
+            _ => return ByteRange::Full,
+        },
+    };
+    match range {
+        Some((start, end)) => ByteRange::Partial { start, end },
+        None => ByteRange::Unsatisfiable,
+    }
+}
+
+/// The value of the `Range` header of a request, if it has one
+fn range_header(req: &Request) -> Option<&str> {
+    req.headers
+        .get_raw("Range")
+        .and_then(|values| values.get(0))
+        .map(|value| std::str::from_utf8(value).unwrap_or_default())
+}
+
+/// Sets `content` as the body of a response, or only the part of it requested with `range`.
+/// Responses with the whole file announce that ranges are supported.
+fn set_content(response: &mut Response, range: Option<&str>, content: Vec<u8>) {
+    let len = content.len();
+    match byte_range(range, len) {
+        ByteRange::Full => {
+            response.headers.set(AcceptRanges(vec![RangeUnit::Bytes]));
+            response.headers.set(ContentLength(len as u64));
+            response.body = Some(Box::new(content));
+        }
+        ByteRange::Partial { start, end } => {
+            let mut content = content;
+            content.truncate(end + 1);
+            content.drain(..start);
+            response.status = Some(status::PartialContent);
+            response.headers.set(ContentRange(ContentRangeSpec::Bytes {
+                range: Some((start as u64, end as u64)),
+                instance_length: Some(len as u64),
+            }));
+            response.headers.set(ContentLength(content.len() as u64));
+            response.body = Some(Box::new(content));
+        }
+        ByteRange::Unsatisfiable => {
+            response.status = Some(status::RangeNotSatisfiable);
+            response.headers.set(ContentRange(ContentRangeSpec::Bytes {
+                range: None,
+                instance_length: Some(len as u64),
+            }));
+            response.headers.set(ContentLength(0));
+            response.body = None;
+        }
+    }
+}
+
+/// Serves the stored contents of a file exactly as they are, with their stored mime type unless
+/// `SERVED_MIME_TYPES` fixes it. UTF-8 text is marked as such. Only the part requested with a
+/// `Range` header is served, see `set_content`.
+fn raw_file(range: Option<&str>, mut blob: Blob) -> Response {
+    let mut content_type = served_mime(&blob.path).map_or(blob.mime, |mime| mime.to_owned());
+    if content_type.starts_with("text/") && std::str::from_utf8(&blob.content).is_ok() {
+        content_type.push_str("; charset=utf-8");
+    }
+
+    let mut response = Response::with(status::Ok);
+    set_content(&mut response, range, std::mem::take(&mut blob.content));
+    response
+        .headers
+        .set_raw("Content-Type", vec![content_type.into_bytes()]);
+    response
+        .headers
+        .set_raw("Content-Disposition", vec![b"inline".to_vec()]);
+    response
+}
+
+/// Highlights the contents of a rendered file on the server if that's enabled, see
+/// `Config::source_server_highlighting`. `language` is the `rendered_language` of
+/// `source_browser`. A file that takes too long to highlight is shown as plain text, without
+/// leaving it to the browser to highlight instead.
+fn server_highlighting(
+    config: &Config,
+    content: &str,
+    language: Option<Option<&str>>,
+) -> Option<Highlighted> {
+    if !config.source_server_highlighting {
+        return None;
+    }
+    highlight(content, language.flatten()?)
+}
+
+/// Measures the steps of serving a source page, reported to browsers in a `Server-Timing` header.
+///
+/// The durations of steps with the same name are added up.
+struct ServerTiming {
+    steps: Vec<(&'static str, Duration)>,
+    current: (&'static str, Instant),
+}
+
+impl ServerTiming {
+    fn new(step: &'static str) -> Self {
+        Self {
+            steps: Vec::new(),
+            current: (step, Instant::now()),
+        }
+    }
+
+    /// Ends the current step and starts the step `step`
+    fn step(&mut self, step: &'static str) {
+        self.finish_current();
+        self.current = (step, Instant::now());
+    }
+
+    fn finish_current(&mut self) {
+        let (current, start) = self.current;
+        let elapsed = start.elapsed();
+        match self.steps.iter_mut().find(|(step, _)| *step == current) {
+            Some((_, total)) => *total += elapsed,
+            None => self.steps.push((current, elapsed)),
+        }
+    }
+
+    /// Ends the current step and adds the `Server-Timing` header to `response`
+    fn set_header(mut self, response: &mut Response) {
+        self.finish_current();
+        let value = self
+            .steps
+            .iter()
+            .map(|(step, duration)| format!("{};dur={:.3}", step, duration.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+        response
+            .headers
+            .set_raw("Server-Timing", vec![value.into_bytes()]);
+    }
+}
+
+/// The page served instead of any source while the source browser is turned off
+fn source_browsing_disabled(req: &Request) -> IronResult<Response> {
+    ErrorPage {
+        title: "Source browsing is temporarily unavailable",
+        message: Some("please try again later".into()),
+        status: status::ServiceUnavailable,
+    }
+    .into_response(req)
+}
+
+/// Serves a request for one of the endpoints covering a whole release, like
+/// `/crate/:name/:version/source.tar.gz`, with `serve` once the release is known. Nothing is
+/// served while the source browser is turned off, and a semver requirement is redirected to the
+/// same URL with the exact version.
+fn resolve_source_release(
+    req: &Request,
+    serve: impl FnOnce(&Request, &str, SourceRelease) -> IronResult<Response>,
+) -> IronResult<Response> {
+    if extension!(req, Config).source_browser_disable_path.exists() {
+        return source_browsing_disabled(req);
+    }
+
+    let router = extension!(req, Router);
+    let name = cexpect!(req, router.find("name"));
+    let req_version = router.find("version");
+    let mut conn = extension!(req, Pool).get()?;
+
+    let version =
+        match match_version(&mut conn, name, req_version).and_then(|m| m.assume_exact())? {
+            MatchSemver::Exact((version, _)) => version,
+            MatchSemver::Semver((version, _)) => {
+                // everything after the version is kept, the path is /crate/:name/:version/...
+                let mut url = format!(
+                    "{}/crate/{}/{}/{}",
+                    redirect_base(req),
+                    encode_path_segment(name),
+                    encode_path_segment(&version),
+                    req.url.path()[3..].join("/"),
+                );
+                if let Some(query) = req.url.query() {
+                    url.push('?');
+                    url.push_str(query);
+                }
+                let url = ctry!(req, Url::parse(&url));
+
+                return Ok(super::redirect(url));
+            }
+        };
+
+    let release = match ctry!(req, SourceRelease::load(&mut conn, name, &version)) {
+        Some(release) => release,
+        None => return Err(Nope::ResourceNotFound.into()),
+    };
+    serve(req, name, release)
+}
+
+/// Whether a release was deleted from docs.rs, which is recorded when deleting it
+fn is_deleted_release(conn: &mut Client, name: &str, version: &str) -> Result<bool> {
+    Ok(conn
+        .query_opt(
+            "SELECT 1 FROM deleted_releases WHERE name = $1 AND version = $2",
+            &[&name, &version],
+        )?
+        .is_some())
+}
+
+/// Forbids browsers from sniffing a different content type than the one a source response
+/// declares, so the served bytes of a file can never be interpreted as e.g. HTML
+fn set_nosniff(response: IronResult<Response>) -> IronResult<Response> {
+    response.map(|mut response| {
+        response
+            .headers
+            .set_raw("X-Content-Type-Options", vec![b"nosniff".to_vec()]);
+        response
+    })
+}
+
+/// Responses smaller than this aren't worth the overhead of compressing them
+const MIN_COMPRESSION_SIZE: u64 = 1024;
+
+/// Whether the client accepts gzip encoded responses, going by its `Accept-Encoding` header
+fn accepts_gzip(req: &Request) -> bool {
+    req.headers
+        .get::<AcceptEncoding>()
+        .map_or(false, |encodings| {
+            encodings
+                .iter()
+                .any(|encoding| encoding.item == Encoding::Gzip && encoding.quality != Quality(0))
+        })
+}
+
+/// Adds `header` to the `Vary` header of a response, keeping the headers it already varies by
+fn add_vary(response: &mut Response, header: &str) {
+    let mut values: Vec<String> = response
+        .headers
+        .get_raw("Vary")
+        .into_iter()
+        .flatten()
+        .filter_map(|value| std::str::from_utf8(value).ok())
+        .flat_map(|value| value.split(','))
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+        .collect();
+    if !values
+        .iter()
+        .any(|value| value == "*" || value.eq_ignore_ascii_case(header))
+    {
+        values.push(header.to_owned());
+    }
+    response
+        .headers
+        .set_raw("Vary", vec![values.join(", ").into_bytes()]);
+}
+
+/// Gzips the body of a successful response if the client supports it.
+///
+/// Only bodies whose length is known up front are compressed, which leaves out streamed
+/// responses like the source tarball, and bodies below [`MIN_COMPRESSION_SIZE`].
+fn compress(req: &Request, response: IronResult<Response>) -> IronResult<Response> {
+    let mut response = response?;
+    let length = match response.headers.get::<ContentLength>() {
+        Some(&ContentLength(length)) => length,
+        None => return Ok(response),
+    };
+    if response.status != Some(status::Ok)
+        || length < MIN_COMPRESSION_SIZE
+        || response.headers.has::<ContentEncoding>()
+    {
+        return Ok(response);
+    }
+
+    // the response differs by encoding from here on, whether it's compressed or not
+    add_vary(&mut response, "Accept-Encoding");
+    if !accepts_gzip(req) {
+        return Ok(response);
+    }
+
+    if let Some(mut body) = response.body.take() {
+        let mut encoder =
+            GzEncoder::new(Vec::with_capacity(length as usize), Compression::default());
+        let compressed = body.write_body(&mut encoder).and_then(|_| encoder.finish());
+        let compressed = ctry!(req, compressed);
+        response.headers.set(ContentLength(compressed.len() as u64));
+        response.headers.set(ContentEncoding(vec![Encoding::Gzip]));
+        // ranges would refer to the compressed bytes, parts of the file are only served as they
+        // are stored
+        response.headers.remove::<AcceptRanges>();
+        response.body = Some(Box::new(compressed));
+    }
+    Ok(response)
+}
+
+/// Derives the strong ETag of a source browser response from its request.
+///
+/// Only the URLs of exact versions are answered with content, everything else is redirected,
+/// and the source of a release never changes. So the URL, what the client accepts and the
+/// version of docs.rs rendering the page identify a response, without looking at its contents.
+/// Whether the release is yanked and the configuration of the source browser change the pages
+/// around the source though, so they're part of the tag as well.
+fn source_etag(req: &Request, config: &Config, yanked: bool) -> String {
+    let rendering = (
+        &config.source_rendered_extensions,
+        &config.source_placeholders,
+        &config.source_script_extensions,
+        &config.source_issue_link_template,
+        config.source_server_highlighting,
+        config.source_render_threshold,
+        config.source_inline_size_limit,
+        config.source_search_max_matches,
+    );
+
+    let mut hasher = Sha256::new();
+    hasher.update(req.url.path().join("/"));
+    hasher.update(b"?");
+    hasher.update(req.url.query().unwrap_or_default());
+    hasher.update(if wants_json(req) { "\0json" } else { "\0html" });
+    hasher.update(if yanked { "\0yanked" } else { "\0published" });
+    hasher.update(b"\0");
+    hasher.update(format!("{:?}", rendering));
+    hasher.update(b"\0");
+    hasher.update(crate::BUILD_VERSION);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Returns the tag of the request's `If-None-Match` header matching `etag`, either for the plain
+/// or for the gzipped response
+fn matching_etag(req: &Request, etag: &str) -> Option<EntityTag> {
+    match req.headers.get::<IfNoneMatch>() {
+        Some(IfNoneMatch::Items(tags)) => tags
+            .iter()
+            .find(|tag| tag.tag() == etag || tag.tag().strip_suffix("-gzip") == Some(etag))
+            .cloned(),
+        _ => None,
+    }
+}
+
+/// Sets the ETag of successful responses, see `source_etag`. Gzipped responses get their own tag,
+/// as their bytes differ.
+fn set_etag(etag: Option<String>, response: IronResult<Response>) -> IronResult<Response> {
+    response.map(|mut response| {
+        if let Some(mut etag) = etag.filter(|_| response.status == Some(status::Ok)) {
+            if response.headers.has::<ContentEncoding>() {
+                etag.push_str("-gzip");
+            }
+            response.headers.set(ETag(EntityTag::strong(etag)));
+        }
+        response
+    })
+}
+
+pub fn source_browser_handler(req: &mut Request) -> IronResult<Response> {
+    let mut etag = None;
+    let response = source_browser(req, &mut etag);
+    let response = compress(req, response);
+    set_nosniff(set_etag(etag, response))
+}
+
+/// A request to the source browser for a path in a release, once the release is loaded
+struct SourceRequest<'a> {
+    crate_name: &'a str,
+    version: &'a str,
+    release: &'a SourceRelease,
+    /// The requested path relative to the crate root, ending with a `/` for directories
+    file_path: &'a str,
+    /// The listed directory, the directory containing the file if a file is requested
+    req_path: &'a str,
+    /// What `file_path` refers to according to the file list of the release
+    kind: PathKind,
+    listing: ListingOptions,
+}
+
+impl SourceRequest<'_> {
+    fn files(&self) -> &Value {
+        self.release.file_list()
+    }
+
+    /// The crate root is requested as `source/`, which leaves an empty `file_path`
+    fn is_directory(&self) -> bool {
+        self.file_path.is_empty() || self.file_path.ends_with('/')
+    }
+}
+
+/// How the listing of a directory is requested in the query
+#[derive(Clone, Copy)]
+struct ListingOptions {
+    dirs_only: bool,
+    show_hidden: bool,
+    order: ListingOrder,
+    /// The number of the requested page, starting at 1
+    page_number: usize,
+    /// The slice of the listing to show, `None` for the whole listing
+    page: Option<ListingPage>,
+}
+
+impl ListingOptions {
+    fn from_request(req: &Request) -> Self {
+        let page_number = query_param(req, "page")
+            .and_then(|page| page.parse::<usize>().ok())
+            .filter(|&page| page > 0)
+            .unwrap_or(1);
+
+        ListingOptions {
+            dirs_only: query_flag(req, "dirs-only"),
+            show_hidden: query_flag(req, "hidden"),
+            order: ListingOrder::from_query(
+                query_param(req, "sort").as_deref(),
+                query_param(req, "dir").as_deref(),
+            ),
+            page_number,
+            // the CSV export always contains the whole listing
+            page: Some(ListingPage::numbered(page_number))
+                .filter(|_| query_param(req, "format").as_deref() != Some("csv")),
+        }
+    }
+
+    /// Lists the directory `path` of the release, see `FileList::from_path`
+    fn list(&self, release: &SourceRelease, path: &str) -> Option<FileList> {
+        FileList::from_path(
+            release,
+            path,
+            self.dirs_only,
+            self.show_hidden,
+            self.order,
+            self.page,
+        )
+    }
+}
+
+/// How the requested file is shown on its page, see `serve_file`
+#[derive(Default)]
+struct RenderedFile {
+    /// The text of the file, `None` if it isn't shown as text
+    content: Option<String>,
+    is_rust_source: bool,
+    /// `Some` if a text file is rendered, containing the language of that file
+    language: Option<Option<&'static str>>,
+    forced_language: Option<&'static str>,
+    type_mismatch: Option<String>,
+    script: bool,
+    placeholder: Option<Placeholder>,
+    hexdump: Option<String>,
+    image_preview: Option<ImagePreview>,
+    detected_encoding: Option<String>,
+    line_count: Option<usize>,
+    byte_size: Option<usize>,
+    truncated: bool,
+}
+
+/// Serves a page of the source browser. `etag` is set to the tag of the response once the
+/// requested release is known, see `source_etag`.
+fn source_browser(req: &mut Request, etag: &mut Option<String>) -> IronResult<Response> {
+    if extension!(req, Config).source_browser_disable_path.exists() {
+        return source_browsing_disabled(req);
+    }
+
+    if req
+        .url
+        .path()
+        .iter()
+        .any(|segment| percent_decode(segment.as_bytes()).decode_utf8().is_err())
+    {
+        return ErrorPage {
+            title: "Invalid path",
+            message: Some("the requested path isn't valid UTF-8".into()),
+            status: status::BadRequest,
+        }
+        .into_response(req);
+    }
+
+    // the stored paths aren't percent-encoded, so neither may the requested ones be
+    let requested_path: Vec<Cow<'_, str>> = req
+        .url
+        .path()
+        .into_iter()
+        // remove first elements from path which is /crate/:name/:version/source
+        .skip(4)
+        .map(|segment| percent_decode(segment.as_bytes()).decode_utf8_lossy())
+        .collect();
+    if let Some(reason) = invalid_source_path(&requested_path.join("/")) {
+        return ErrorPage {
+            title: "Invalid path",
+            message: Some(reason.into()),
+            status: status::BadRequest,
+        }
+        .into_response(req);
+    }
+
+    let router = extension!(req, Router);
+    let mut crate_name = cexpect!(req, router.find("name"));
+    let req_version = cexpect!(req, router.find("version"));
+    let timing = ServerTiming::new("db");
+    let pool = extension!(req, Pool);
+    let mut conn = pool.get()?;
+
+    let mut req_path = req.url.path();
+    // remove first elements from path which is /crate/:name/:version/source
+    req_path.drain(0..4);
+
+    // the version arrives percent-encoded, e.g. `1.0.0%2Bbuild` for `1.0.0+build`, and is
+    // compared to its canonical form only after decoding it
+    let decoded_version = percent_decode(req_version.as_bytes()).decode_utf8_lossy();
+    if decoded_version.trim().is_empty() {
+        return ErrorPage {
+            title: "Invalid version",
+            message: Some("the requested version is empty".into()),
+            status: status::BadRequest,
+        }
+        .into_response(req);
+    }
+    let normalized_version = normalize_version(decoded_version.trim());
+    let v = match match_version(&mut conn, crate_name, Some(&*normalized_version)) {
+        Ok(v) => v,
+        // a deleted release won't come back, unlike one that doesn't exist yet
+        Err(err) => {
+            if ctry!(
+                req,
+                is_deleted_release(&mut conn, crate_name, &normalized_version)
+            ) {
+                return ErrorPage {
+                    title: "Release removed",
+                    message: Some("this release was removed from docs.rs".into()),
+                    status: status::Gone,
+                }
+                .into_response(req);
+            }
+            return Err(err.into());
+        }
+    };
+    if let Some(new_name) = &v.corrected_name {
+        // `match_version` checked against -/_ typos, so if we have a name here we should
+        // use that instead
+        crate_name = new_name;
+    }
+    let version = match v.version {
+        MatchSemver::Exact((version, _))
+            if normalized_version == decoded_version && v.corrected_name.is_none() =>
+        {
+            version
+        }
+        // a version that only matched after normalization is redirected to its canonical form,
+        // just like semver requirements are. A corrected name is applied in the same redirect,
+        // so a typo'd name with a semver requirement only takes a single hop.
+        MatchSemver::Exact((version, _)) | MatchSemver::Semver((version, _)) => {
+            let mut url = format!(
+                "{}/crate/{}/{}/source/{}",
+                redirect_base(req),
+                encode_path_segment(crate_name),
+                encode_path_segment(&version),
+                req_path.join("/"),
+            );
+            // keep options like `?dirs-only=1` across the redirect
+            if let Some(query) = req.url.query() {
+                url.push('?');
+                url.push_str(query);
+            }
+            let url = ctry!(req, Url::parse(&url));
+
+            // tell clients what the requested version resolved to, to make it easier to find out
+            // why e.g. `*` picked a certain release
+            let mut response = super::redirect(url);
+            response.headers.set_raw(
+                "X-Docsrs-Version-Spec",
+                vec![decoded_version.as_bytes().to_vec()],
+            );
+            response
+                .headers
+                .set_raw("X-Docsrs-Resolved-Version", vec![version.into_bytes()]);
+            return Ok(response);
+        }
+    };
+
+    // get path (req_path) for FileList::from_path and actual path for super::file::File::from_path
+    let (req_path, file_path) = {
+        let mut req_path = requested_path;
+        let file_path = req_path.join("/");
+
+        // FileList::from_path is only working for directories
+        // remove file name if it's not a directory
+        if let Some(last) = req_path.last_mut() {
+            if !last.is_empty() {
+                *last = "".into();
+            }
+        }
+
+        // remove crate name and version from req_path
+        let path = req_path
+            .join("/")
+            .replace(&format!("{}/{}/", crate_name, version), "");
+
+        (path, file_path)
+    };
+
+    // everything below works with this single copy of the release
+    let release = match ctry!(req, SourceRelease::load(&mut conn, crate_name, &version)) {
+        Some(release) => release,
+        None => return Err(Nope::ResourceNotFound.into()),
+    };
+
+    // the client has the current response already, see `source_etag`
+    let current_etag = source_etag(req, extension!(req, Config), release.metadata.yanked);
+    if let Some(etag) = matching_etag(req, &current_etag) {
+        let mut response = Response::with(status::NotModified);
+        response.headers.set(ETag(etag));
+        if query_flag(req, "raw") || query_param(req, "download").as_deref() == Some("1") {
+            set_immutable_cache_headers(&mut response, crate_name);
+        } else {
+            set_cache_headers(&mut response, crate_name);
+        }
+        return Ok(response);
+    }
+    *etag = Some(current_etag);
+
+    let source = SourceRequest {
+        crate_name,
+        version: &version,
+        release: &release,
+        file_path: &file_path,
+        req_path: &req_path,
+        kind: classify_path(release.file_list(), &file_path),
+        listing: ListingOptions::from_request(req),
+    };
+
+    if query_param(req, "debug").as_deref() == Some("files-json")
+        && extension!(req, Config).source_browser_debug
+    {
+        let files = match &release.files {
+            Some(files) => files,
+            None => return Err(Nope::ResourceNotFound.into()),
+        };
+
+        let mut resp = Response::with((status::Ok, serde_json::to_string_pretty(files).unwrap()));
+        resp.headers.set(ContentType::json());
+        return Ok(resp);
+    }
+
+    if query_flag(req, "probe") {
+        let body = json!({
+            "exists": source.kind != PathKind::Missing,
+            "kind": source.kind,
+        });
+
+        let mut resp = Response::with((status::Ok, body.to_string()));
+        resp.headers.set(ContentType::json());
+        return Ok(resp);
+    }
+
+    if source.is_directory() {
+        return serve_directory(req, &mut conn, &source, timing);
+    }
+    serve_file(req, &mut conn, &source, timing)
+}
+
+/// Serves a directory of a release, as a listing or in one of the formats asked for in the query
+fn serve_directory(
+    req: &Request,
+    conn: &mut Client,
+    source: &SourceRequest<'_>,
+    mut timing: ServerTiming,
+) -> IronResult<Response> {
+    let (crate_name, version, file_path) = (source.crate_name, source.version, source.file_path);
+    let storage = extension!(req, Storage);
+    let archive_storage = source.release.archive_storage;
+
+    if let Some(query) = query_param(req, "search").filter(|query| !query.is_empty()) {
+        let files = match &source.release.files {
+            Some(files) => files,
+            None => return Err(Nope::ResourceNotFound.into()),
+        };
+        let results = search_files(
+            storage,
+            crate_name,
+            version,
+            archive_storage,
+            files,
+            file_path,
+            &query,
+            extension!(req, Config).source_search_max_matches,
+        );
+
+        let mut resp = Response::with((status::Ok, serde_json::to_string(&results).unwrap()));
+        resp.headers.set(ContentType::json());
+        set_cache_headers(&mut resp, crate_name);
+        return Ok(resp);
+    }
+
+    if query_param(req, "download").as_deref() == Some("zip") {
+        let paths = directory_file_paths(source.files(), file_path, query_flag(req, "recursive"));
+        if paths.is_empty() {
+            return Err(Nope::ResourceNotFound.into());
+        }
+
+        let zip = match ctry!(
+            req,
+            directory_zip(storage, crate_name, version, archive_storage, &paths)
+        ) {
+            Some(zip) => zip,
+            None => {
+                return ErrorPage {
+                    title: "The requested directory is too large to download",
+                    message: Some(
+                        format!(
+                            "the files in this directory exceed the limit of {} MiB",
+                            MAX_DIRECTORY_DOWNLOAD_SIZE / 1024 / 1024
+                        )
+                        .into(),
+                    ),
+                    status: status::BadRequest,
+                }
+                .into_response(req);
+            }
+        };
+
+        let filename = format!("{}-{}", crate_name, version)
+            + &file_path
+                .trim_end_matches('/')
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(|segment| format!("-{}", segment))
+                .collect::<String>();
+        let mut resp = Response::with((status::Ok, zip));
+        resp.headers
+            .set(ContentType("application/zip".parse().unwrap()));
+        resp.headers.set_raw(
+            "Content-Disposition",
+            vec![format!("attachment; filename=\"{}.zip\"", filename).into_bytes()],
+        );
+        return Ok(resp);
+    }
+
+    // only files have stored bytes or a rendering to embed
+    if query_flag(req, "raw") || query_flag(req, "fragment") {
+        return Err(Nope::ResourceNotFound.into());
+    }
+
+    timing.step("render");
+    let file_list = match source.listing.list(source.release, source.req_path) {
+        Some(file_list) => file_list,
+        // a file requested with a trailing slash is redirected to the file itself instead of
+        // being reported as a missing directory
+        None if !file_path.is_empty() && source.kind == PathKind::File => {
+            let mut url = format!(
+                "{}{}",
+                redirect_base(req),
+                permalink(crate_name, version, file_path.trim_end_matches('/')),
+            );
+            if let Some(query) = req.url.query() {
+                url.push('?');
+                url.push_str(query);
+            }
+            let url = ctry!(req, Url::parse(&url));
+
+            return Ok(Response::with((status::MovedPermanently, Redirect(url))));
+        }
+        None if wants_json(req) => {
+            return Ok(path_not_found_json(
+                source.files(),
+                crate_name,
+                version,
+                file_path,
+            ));
+        }
+        None => return Err(Nope::ResourceNotFound.into()),
+    };
+
+    if source.listing.page_number > 1 && file_list.files.is_empty() {
+        return Err(Nope::ResourceNotFound.into());
+    }
+
+    if wants_json(req) {
+        let mut response = Response::with((status::Ok, serde_json::to_string(&file_list).unwrap()));
+        response.headers.set(ContentType::json());
+        set_cache_headers(&mut response, crate_name);
+        timing.set_header(&mut response);
+        return Ok(response);
+    }
+    if query_param(req, "format").as_deref() == Some("csv") {
+        let csv = ctry!(req, listing_csv(&file_list.files));
+        let mut response = Response::with((status::Ok, csv));
+        response
+            .headers
+            .set(ContentType("text/csv; charset=utf-8".parse().unwrap()));
+        set_cache_headers(&mut response, crate_name);
+        timing.set_header(&mut response);
+        return Ok(response);
+    }
+
+    render_source_page(
+        req,
+        conn,
+        source,
+        file_list,
+        RenderedFile::default(),
+        timing,
+    )
+}
+
+/// Serves the stored bytes of a file as they are, for tools asking for `?raw=1`
+fn serve_raw_file(
+    req: &Request,
+    crate_name: &str,
+    blob: Option<Blob>,
+    timing: ServerTiming,
+) -> IronResult<Response> {
+    match blob {
+        Some(blob) => {
+            let mut response = raw_file(range_header(req), blob);
+            set_immutable_cache_headers(&mut response, crate_name);
+            timing.set_header(&mut response);
+            Ok(response)
+        }
+        None => Err(Nope::ResourceNotFound.into()),
+    }
+}
+
+/// Serves a file of a release, rendered on a page with the listing of its directory or in one of
+/// the forms asked for in the query
+fn serve_file(
+    req: &Request,
+    conn: &mut Client,
+    source: &SourceRequest<'_>,
+    mut timing: ServerTiming,
+) -> IronResult<Response> {
+    let (crate_name, version, file_path) = (source.crate_name, source.version, source.file_path);
+    let config = extension!(req, Config);
+
+    timing.step("storage");
+    let storage = extension!(req, Storage);
+    let (blob, oversized) = match storage.fetch_source_file(
+        crate_name,
+        version,
+        file_path,
+        source.release.archive_storage,
+    ) {
+        Ok(blob) => (Some(blob), false),
+        Err(err) => (None, is_size_limit_error(&err)),
+    };
+
+    // a directory requested without a trailing slash is redirected to its canonical form, the
+    // other way around than for files requested with one
+    if blob.is_none() && !oversized && source.kind == PathKind::Dir {
+        let mut url = format!(
+            "{}{}",
+            redirect_base(req),
+            permalink(crate_name, version, &format!("{}/", file_path)),
+        );
+        if let Some(query) = req.url.query() {
+            url.push('?');
+            url.push_str(query);
+        }
+        let url = ctry!(req, Url::parse(&url));
+
+        return Ok(Response::with((status::MovedPermanently, Redirect(url))));
+    }
+
+    // the file list of the release decides what can be browsed, a file that's only in the
+    // storage is a stale leftover. Releases without a file list can't be checked.
+    let listed = source
+        .files()
+        .as_array()
+        .map_or(false, |files| !files.is_empty());
+    if blob.is_some() && listed && source.kind != PathKind::File {
+        log::warn!(
+            "'{}' of {} {} is in the storage, but not in the file list",
+            file_path,
+            crate_name,
+            version
+        );
+        if wants_json(req) {
+            return Ok(path_not_found_json(
+                source.files(),
+                crate_name,
+                version,
+                file_path,
+            ));
+        }
+        return Err(Nope::ResourceNotFound.into());
+    }
+    timing.step("render");
+
+    // tools can fetch the stored bytes of a file as they are, without any of the handling below
+    if query_flag(req, "raw") {
+        return serve_raw_file(req, crate_name, blob, timing);
+    }
+
+    if blob.is_none() && wants_json(req) {
+        return Ok(path_not_found_json(
+            source.files(),
+            crate_name,
+            version,
+            file_path,
+        ));
+    }
+
+    let mut file = RenderedFile {
+        script: has_extension(&config.source_script_extensions, file_path),
+        ..RenderedFile::default()
+    };
+    let placeholders = &config.source_placeholders;
+    let file_name = file_path.rsplit('/').next().unwrap_or_default().to_owned();
+    let (content, is_rust_source) = if let Some(mut blob) = blob {
+        // a stored mime type contradicting the extension is pointed out on the page, see
+        // `is_text_file` for how the contents decide whether this is a text file then
+        let is_empty = blob.is_empty();
+        let mismatch = !is_empty && mime_disagrees_with_extension(file_path, &blob.mime);
+        let is_text = is_text_file(file_path, &blob, file.script);
+        let too_large = is_too_large_to_render(config, blob.content.len());
+
+        // text that isn't UTF-8 is transcoded if its encoding can be told, see `transcode`. Large
+        // files aren't rendered, so they aren't transcoded either.
+        let transcoded =
+            if is_text && !is_empty && !too_large && std::str::from_utf8(&blob.content).is_err() {
+                Some(transcode(&blob.content))
+            } else {
+                None
+            };
+        let undecodable = matches!(transcoded, Some(None));
+
+        // API clients get the text itself instead of a page rendering it
+        if wants_json(req) {
+            let size = blob.content.len();
+            let content = if is_empty {
+                Some(String::new())
+            } else if !is_text {
+                None
+            } else {
+                match transcoded {
+                    Some(transcoded) => transcoded.map(|(content, _)| content),
+                    None => String::from_utf8(blob.content).ok(),
+                }
+            };
+            let mut response = file_json(
+                file_path,
+                &blob.mime,
+                blob.path.ends_with(".rs"),
+                size,
+                content.map(strip_bom),
+                format!(
+                    "{}{}?download=1",
+                    redirect_base(req),
+                    permalink(crate_name, version, file_path)
+                ),
+                query_param(req, "fields").as_deref(),
+            );
+            set_cache_headers(&mut response, crate_name);
+            timing.set_header(&mut response);
+            return Ok(response);
+        }
+
+        // serve the file with DatabaseFileHandler if file isn't text and not empty, unless a
+        // placeholder is shown for binary files. The same goes for text that can't be decoded,
+        // and for text that's too large to be rendered, see `is_too_large_to_render`.
+        let download = query_param(req, "download").as_deref() == Some("1");
+        let binary_placeholder = placeholders.iter().any(|kind| kind == "binary");
+        let unsupported_placeholder = placeholders.iter().any(|kind| kind == "unsupported");
+        let large_placeholder = placeholders.iter().any(|kind| kind == "large");
+        let preview = !is_empty
+            && !is_text
+            && is_hexdump_previewed(&blob.mime, blob.content.len())
+            && !looks_like_text(&blob.content);
+        let image = !is_empty && !mismatch && PREVIEWED_IMAGE_TYPES.contains(&&*blob.mime);
+        if !is_empty
+            && (download
+                || (!is_text && !binary_placeholder && !preview && !image)
+                || (undecodable && !unsupported_placeholder)
+                || (is_text && too_large && !large_placeholder))
+        {
+            if mismatch {
+                if let Some(mime) = mime_guess::from_path(file_path).first_raw() {
+                    blob.mime = mime.to_owned();
+                }
+            }
+            if let Some(mime) = served_mime(file_path) {
+                blob.mime = mime.to_owned();
+            }
+            let content = std::mem::take(&mut blob.content);
+            let mut response = DbFile(blob).serve();
+            set_content(&mut response, range_header(req), content);
+            set_file_type_headers(&mut response, is_text && !undecodable, None);
+            set_immutable_cache_headers(&mut response, crate_name);
+            timing.set_header(&mut response);
+            if mismatch {
+                response
+                    .headers
+                    .set_raw("X-Docsrs-Type-Mismatch", vec![b"true".to_vec()]);
+            }
+            if download {
+                response.headers.set_raw(
+                    "Content-Disposition",
+                    vec![format!("attachment; filename=\"{}\"", file_name).into_bytes()],
+                );
+            }
+            return Ok(response);
+        }
+        let allowed_extensions = config.source_rendered_extensions.as_deref();
+        if image {
+            // the page links to the image, SVGs included, so scripts in an SVG never run in the
+            // page and the image itself is served with its own restrictive CSP
+            file.image_preview = Some(ImagePreview {
+                name: file_name.clone(),
+                size: blob.content.len(),
+                dimensions: image_dimensions(&blob.mime, &blob.content),
+            });
+        } else if preview {
+            file.hexdump = Some(hexdump(&blob.content));
+        } else if !is_text && !is_empty {
+            file.placeholder = Some(Placeholder {
+                kind: "binary",
+                name: file_name.clone(),
+                mime: Some(blob.mime.clone()),
+                size: Some(blob.content.len()),
+                downloadable: true,
+            });
+        } else if file.script {
+            // scripts are never rendered as anything but plain text, see
+            // `Config::source_script_extensions`
+            file.forced_language = Some("plaintext");
+            file.language = Some(None);
+        } else if rendering_allowed(allowed_extensions, file_path) {
+            file.forced_language = query_param(req, "lang").and_then(|lang| known_language(&lang));
+            file.language = Some(
+                file.forced_language
+                    .or_else(|| language_for_path(file_path)),
+            );
+        } else {
+            // keep the highlighter from detecting a language by itself
+            file.forced_language = Some("plaintext");
+            file.language = Some(None);
+        }
+        let mime = blob.mime.clone();
+        if mismatch {
+            file.type_mismatch = Some(blob.mime);
+        }
+        if is_text && too_large {
+            // only reached with the `large` placeholder, the file is served as it is otherwise
+            file.placeholder = Some(Placeholder {
+                kind: "large",
+                name: file_name.clone(),
+                mime: Some(mime),
+                size: Some(blob.content.len()),
+                downloadable: true,
+            });
+            (None, false)
+        } else if is_text && !is_empty {
+            let size = blob.content.len();
+            let content = match transcoded {
+                Some(transcoded) => transcoded.map(|(content, encoding)| {
+                    file.detected_encoding = Some(encoding.to_owned());
+                    content
+                }),
+                None => String::from_utf8(blob.content).ok(),
+            };
+            match content {
+                Some(content) => {
+                    file.byte_size = Some(size);
+                    (Some(strip_bom(content)), blob.path.ends_with(".rs"))
+                }
+                None => {
+                    if unsupported_placeholder {
+                        file.placeholder = Some(Placeholder {
+                            kind: "unsupported",
+                            name: file_name.clone(),
+                            mime: Some(mime),
+                            size: Some(size),
+                            downloadable: true,
+                        });
+                    }
+                    (None, false)
+                }
+            }
+        } else {
+            (None, false)
+        }
+    } else {
+        if oversized && placeholders.iter().any(|kind| kind == "oversized") {
+            file.placeholder = Some(Placeholder {
+                kind: "oversized",
+                name: file_name.clone(),
+                mime: None,
+                size: None,
+                downloadable: false,
+            });
+        }
+        (None, false)
+    };
+    file.content = content;
+    file.is_rust_source = is_rust_source;
+
+    // a huge file would make for a huge page, only its beginning is shown. It's counted in full.
+    file.line_count = file.content.as_deref().map(line_count);
+    file.truncated = file.content.as_mut().map_or(false, |content| {
+        truncate_content(content, config.source_inline_size_limit)
+    });
+
+    // only the rendered file is served for embedding it elsewhere, there's no listing to build
+    if query_flag(req, "fragment") {
+        let file_content = match file.content {
+            Some(file_content) => file_content,
+            None => return Err(Nope::ResourceNotFound.into()),
+        };
+        let mut language = file.forced_language.or_else(|| file.language.flatten());
+        let is_rust = file.is_rust_source || language == Some("rust");
+        let highlighted = server_highlighting(config, &file_content, file.language);
+        if highlighted == Some(Highlighted::TimedOut) {
+            language = Some("plaintext");
+        }
+        let mut response = SourceFragment {
+            lines: source_lines(&file_content, is_rust),
+            highlighted_content: highlighted.and_then(Highlighted::into_html),
+            file_content,
+            language,
+        }
+        .into_response(req)?;
+        set_file_type_headers(&mut response, true, language);
+        set_cache_headers(&mut response, crate_name);
+        timing.set_header(&mut response);
+
+        return Ok(response);
+    }
+
+    let file_list = match source.listing.list(source.release, source.req_path) {
+        Some(file_list) => file_list,
+        // a file that was found is shown even if its directory can't be listed
+        None if file.language.is_some() => FileList {
+            metadata: source.release.metadata.clone(),
+            files: Vec::new(),
+            truncated: false,
+            total: 0,
+        },
+        None => return Err(Nope::ResourceNotFound.into()),
+    };
+
+    render_source_page(req, conn, source, file_list, file, timing)
+}
+
+/// Returns the latest release that isn't yanked and the link to `path` in it, or to that
+/// release's root if the path doesn't exist there. `None` if every release is yanked.
+fn unyanked_link(conn: &mut Client, name: &str, path: &str) -> Result<Option<(String, String)>> {
+    let (latest, id) = match match_version(conn, name, None) {
+        Ok(latest) => latest.version.into_parts(),
+        Err(_) => return Ok(None),
+    };
+    let files: Option<Value> = conn
+        .query_opt("SELECT files FROM releases WHERE id = $1", &[&id])?
+        .and_then(|row| row.get(0));
+
+    let path = match files {
+        Some(files) if classify_path(&files, path) != PathKind::Missing => path,
+        _ => "",
+    };
+    let link = permalink(name, &latest, path);
+    Ok(Some((latest, link)))
+}
+
+/// Renders the page of a directory or of a file, which shows the listing of its directory next
+/// to the file
+fn render_source_page(
+    req: &Request,
+    conn: &mut Client,
+    source: &SourceRequest<'_>,
+    mut file_list: FileList,
+    file: RenderedFile,
+    mut timing: ServerTiming,
+) -> IronResult<Response> {
+    let (crate_name, version, file_path, req_path) = (
+        source.crate_name,
+        source.version,
+        source.file_path,
+        source.req_path,
+    );
+    let files = source.files();
+    let storage = extension!(req, Storage);
+    let archive_storage = source.release.archive_storage;
+
+    let workspace_members = workspace_members(files)
+        .into_iter()
+        .map(|path| WorkspaceMember {
+            link: format!("{}/", permalink(crate_name, version, &path)),
+            path,
+        })
+        .collect();
+    // the stats only cover the whole crate, so they're only shown at its root
+    let (language_stats, tree_shape) = if req_path.is_empty() {
+        let caches = extension!(req, SourceCaches);
+        (
+            caches
+                .language_stats
+                .get_or_insert_with(crate_name, version, || language_stats(files)),
+            caches
+                .tree_shape
+                .get_or_insert_with(crate_name, version, || tree_shape(files)),
+        )
+    } else {
+        (Vec::new(), None)
+    };
+
+    // a yanked release links to the same path in the latest release that isn't yanked
+    let unyanked_link = if source.release.metadata.yanked {
+        ctry!(req, unyanked_link(conn, crate_name, file_path))
+    } else {
+        None
+    };
+
+    // a file links to its neighbors in the listing of its directory, which has to be loaded in
+    // full if only a page of it or only its subdirectories were listed
+    let (prev_file, next_file) = if source.is_directory() {
+        (None, None)
+    } else {
+        let name = file_path.rsplit('/').next().unwrap_or_default();
+        let full_listing = if source.listing.dirs_only || file_list.total > file_list.files.len() {
+            let options = ListingOptions {
+                dirs_only: false,
+                page: None,
+                ..source.listing
+            };
+            options.list(source.release, req_path)
+        } else {
+            None
+        };
+        let siblings = full_listing.as_ref().unwrap_or(&file_list);
+        let (prev_file, next_file) = file_neighbors(&siblings.files, name);
+        (
+            prev_file.map(encode_path_segment),
+            next_file.map(encode_path_segment),
+        )
+    };
+
+    // an unknown version to compare with is ignored, the listing just isn't annotated
+    let previous_release = match query_param(req, "changed-since") {
+        Some(previous) => match match_version(conn, crate_name, Some(previous.as_str())) {
+            Ok(previous) => {
+                let (previous, _) = previous.version.into_parts();
+                ctry!(req, SourceRelease::load(conn, crate_name, &previous))
+            }
+            Err(_) => None,
+        },
+        None => None,
+    };
+
+    timing.step("storage");
+    if req_path.starts_with("src/") {
+        let public_paths = extension!(req, SourceCaches)
+            .public_module_paths
+            .get_or_insert_with(crate_name, version, || {
+                public_module_paths(storage, crate_name, version, archive_storage)
+            });
+        for entry in &mut file_list.files {
+            entry.public = public_paths.contains(&format!("{}{}", req_path, entry.name));
+        }
+    }
+
+    if let Some(previous_release) = &previous_release {
+        let current = ReleaseSource {
+            version,
+            archive_storage,
+            files,
+        };
+        let previous = ReleaseSource {
+            version: &previous_release.metadata.version,
+            archive_storage: previous_release.archive_storage,
+            files: previous_release.file_list(),
+        };
+
+        let mut budget = MAX_CHANGE_COMPARISONS;
+        for entry in &mut file_list.files {
+            let mut path = format!("{}{}", req_path, entry.name);
+            if entry.mime == "dir" {
+                path.push('/');
+            }
+            entry.change =
+                entry_change(storage, crate_name, &current, &previous, &path, &mut budget);
+        }
+    }
+
+    // like on code forges, a directory's README is shown below its listing
+    let directory_readme = if source.is_directory() && file.content.is_none() {
+        directory_readme_name(&file_list.files).and_then(|name| {
+            let path = format!("{}{}", file_path, name);
+            let blob = storage
+                .fetch_source_file(crate_name, version, &path, archive_storage)
+                .ok()?;
+            let content = String::from_utf8(blob.content).ok()?;
+            Some(render_readme(name, &content))
+        })
+    } else {
+        None
+    };
+
+    timing.step("render");
+    let is_rust = file.is_rust_source || file.forced_language == Some("rust");
+    let mut lines = file
+        .content
+        .as_ref()
+        .map(|content| source_lines(content, is_rust))
+        .unwrap_or_default();
+    // tools that can't build a `#L120` fragment link to `?line=120` instead. Lines past the end of
+    // the file are clamped to it, unless `?strict=1` asks for a 404.
+    if let Some(line) = query_param(req, "line").and_then(|line| line.parse::<usize>().ok()) {
+        if query_flag(req, "strict") && (line == 0 || line > lines.len()) {
+            return Err(Nope::ResourceNotFound.into());
+        }
+        let line_count = lines.len();
+        if let Some(target) = lines.get_mut(line.max(1).min(line_count).saturating_sub(1)) {
+            target.target = true;
+        }
+    }
+    let config = extension!(req, Config);
+    let issue_link = match (
+        &config.source_issue_link_template,
+        &source.release.repository_url,
+    ) {
+        (Some(template), Some(repository)) if file.content.is_some() => {
+            Some(issue_link(template, repository, file_path))
+        }
+        _ => None,
+    };
+    let syntax_check = file
+        .content
+        .as_deref()
+        .filter(|_| is_rust && !file.truncated && query_flag(req, "check"))
+        .map(check_syntax);
+    let highlighted = file
+        .content
+        .as_deref()
+        .and_then(|content| server_highlighting(config, content, file.language));
+    let highlight_timed_out = highlighted == Some(Highlighted::TimedOut);
+    let forced_language = if highlight_timed_out {
+        Some("plaintext")
+    } else {
+        file.forced_language
+    };
+    let highlighted_content = highlighted.and_then(Highlighted::into_html);
+    let cargo_toml_deps = file
+        .content
+        .as_deref()
+        .filter(|_| file_path.rsplit('/').next() == Some("Cargo.toml"))
+        .and_then(cargo_toml_deps);
+    let whitespace_only = file.content.as_ref().map_or(false, |content| {
+        !content.is_empty() && content.trim().is_empty()
+    });
+    let listing_groups = if file_list.files.len() > GROUPED_LISTING_SIZE
+        && query_param(req, "group").as_deref() != Some("0")
+    {
+        listing_groups(&file_list.files)
+    } else {
+        Vec::new()
+    };
+    let pagination = Pagination::new(req, source.listing.page_number, file_list.total);
+    let docs_link = file
+        .content
+        .as_ref()
+        .and_then(|_| docs_link(&file_list.metadata, file_path));
+    let is_image = file.image_preview.is_some();
+    let mut response = SourcePage {
+        file_list,
+        listing_order: source.listing.order,
+        sort_links: sort_links(req, source.listing.order),
+        pagination,
+        max_listed_directories: MAX_LISTED_DIRECTORIES,
+        listing_groups,
+        show_parent_link: !req_path.is_empty(),
+        file_content: file.content,
+        highlighted_content,
+        highlight_timed_out,
+        lines,
+        line_count: file.line_count,
+        byte_size: file.byte_size,
+        truncated: file.truncated,
+        whitespace_only,
+        listing_query: listing_query(req),
+        subdirectory_query: subdirectory_query(req),
+        breadcrumbs: breadcrumbs(crate_name, version, req_path),
+        permalink: permalink(crate_name, version, file_path),
+        unyanked_link,
+        prev_file,
+        next_file,
+        workspace_members,
+        language_stats,
+        tree_shape,
+        is_rust_source: file.is_rust_source,
+        forced_language,
+        type_mismatch: file.type_mismatch,
+        script: file.script,
+        syntax_check,
+        issue_link,
+        docs_link,
+        placeholder: file.placeholder,
+        directory_readme,
+        is_binary_preview: file.hexdump.is_some(),
+        hexdump: file.hexdump,
+        image_preview: file.image_preview,
+        detected_encoding: file.detected_encoding,
+        cargo_toml_deps,
+    }
+    .into_response(req)?;
+
+    if let Some(language) = file.language {
+        set_file_type_headers(&mut response, true, language);
+    } else if is_image {
+        set_file_type_headers(&mut response, false, None);
+    }
+    set_cache_headers(&mut response, crate_name);
+    timing.set_header(&mut response);
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{storage::Blob, test::*};
+    use chrono::Utc;
+    use test_case::test_case;
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn fetch_source_file_content(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("some_filename.rs", b"some_random_content")
+                .create()?;
+            let web = env.frontend();
+            assert_success("/crate/fake/0.1.0/source/", web)?;
+            let response = web
+                .get("/crate/fake/0.1.0/source/some_filename.rs")
+                .send()?;
+            assert!(response.status().is_success());
+            assert!(response.text()?.contains("some_random_content"));
+            Ok(())
+        });
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn directory_not_found(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("mbedtls")
+                .version("0.2.0")
+                .create()?;
+            let web = env.frontend();
+            assert_not_found("/crate/mbedtls/0.2.0/source/test/", web)?;
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn semver_handled(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("mbedtls")
+                .version("0.2.0")
+                .source_file("README.md", b"hello")
+                .create()?;
+            let web = env.frontend();
+            assert_success("/crate/mbedtls/0.2.0/source/", web)?;
+            assert_redirect(
+                "/crate/mbedtls/*/source/",
+                "/crate/mbedtls/0.2.0/source/",
+                web,
+            )?;
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn literal_krate_description(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("rustc-ap-syntax")
+                .version("178.0.0")
+                .description("some stuff with krate")
+                .source_file("fold.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+            assert_success("/crate/rustc-ap-syntax/178.0.0/source/fold.rs", web)?;
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn json_path_not_found_hints_closest_directory(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/net/tcp.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/net/missing/udp.rs")
+                .header("Accept", "application/json")
+                .send()?;
+            assert_eq!(response.status(), 404);
+            assert_eq!(
+                response.json::<serde_json::Value>()?,
+                serde_json::json!({
+                    "error": "path_not_found",
+                    "crate": "fake",
+                    "version": "0.1.0",
+                    "path": "src/net/missing/udp.rs",
+                    "closest_directory": "src/net/",
+                }),
+            );
+            Ok(())
+        })
+    }
+
+    #[test_case("1.0")]
+    #[test_case("01.0.0")]
+    #[test_case("1.00.0")]
+    fn non_canonical_version_redirects(req_version: &str) {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("1.0.0")
+                .source_file("README.md", b"hello")
+                .create()?;
+            let web = env.frontend();
+            assert_redirect(
+                &format!("/crate/fake/{}/source/", req_version),
+                "/crate/fake/1.0.0/source/",
+                web,
+            )?;
+            assert_redirect(
+                &format!("/crate/fake/{}/source/README.md", req_version),
+                "/crate/fake/1.0.0/source/README.md",
+                web,
+            )?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn normalize_version() {
+        assert_eq!(super::normalize_version("1.0.0"), "1.0.0");
+        assert_eq!(super::normalize_version("01.0.0"), "1.0.0");
+        assert_eq!(super::normalize_version("1.00.010"), "1.0.10");
+        assert_eq!(
+            super::normalize_version("01.0.0-alpha.01"),
+            "1.0.0-alpha.01"
+        );
+        assert_eq!(super::normalize_version("^01.2"), "^01.2");
+    }
+
+    #[test]
+    fn root_with_and_without_trailing_slash() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("README.md", b"hello")
+                .create()?;
+            let web = env.frontend();
+
+            assert_redirect("/crate/fake/0.1.0/source", "/crate/fake/0.1.0/source/", web)?;
+            for path in &["/crate/fake/0.1.0/source", "/crate/fake/0.1.0/source/"] {
+                let page = web.get(path).send()?.text()?;
+                assert!(page.contains(r#"href="./README.md""#));
+                assert!(!page.contains(r#"href="../""#));
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn directory_without_trailing_slash() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/net tcp/mod.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            assert_redirect(
+                "/crate/fake/0.1.0/source/src",
+                "/crate/fake/0.1.0/source/src/",
+                web,
+            )?;
+            assert_redirect(
+                "/crate/fake/0.1.0/source/src/net%20tcp?sort=size",
+                "/crate/fake/0.1.0/source/src/net%20tcp/?sort=size",
+                web,
+            )?;
+
+            let response = web.get("/crate/fake/0.1.0/source/src").send()?;
+            assert!(response.text()?.contains(r#"href="./lib.rs""#));
+            // files aren't redirected
+            let response = web.get("/crate/fake/0.1.0/source/src/lib.rs").send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.url().path(), "/crate/fake/0.1.0/source/src/lib.rs");
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn file_type_headers(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("logo.png", b"\x89PNG\r\n\x1a\n")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/src/lib.rs").send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["X-Docsrs-Is-Text"], "true");
+            assert_eq!(response.headers()["X-Docsrs-Language"], "rust");
+
+            let response = web.get("/crate/fake/0.1.0/source/logo.png").send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["X-Docsrs-Is-Text"], "false");
+            assert!(response.headers().get("X-Docsrs-Language").is_none());
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn byte_order_mark_is_stripped(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"\xef\xbb\xbffn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains("<code>fn foo() {}</code>"));
+            assert!(!page.contains('\u{feff}'));
+
+            // the stored file is left untouched
+            let blob =
+                env.storage()
+                    .fetch_source_file("fake", "0.1.0", "src/lib.rs", archive_storage)?;
+            assert!(blob.content.starts_with(b"\xef\xbb\xbf"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn mismatched_mime_types_are_sniffed() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("logo.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")
+                .create()?;
+            env.storage().store_blobs(vec![
+                Blob {
+                    path: "sources/fake/0.1.0/src/lib.rs".into(),
+                    mime: "application/octet-stream".into(),
+                    date_updated: Utc::now(),
+                    content: b"fn foo() {}".to_vec(),
+                    compression: None,
+                },
+                Blob {
+                    path: "sources/fake/0.1.0/logo.png".into(),
+                    mime: "text/plain".into(),
+                    date_updated: Utc::now(),
+                    content: b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec(),
+                    compression: None,
+                },
+            ])?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/src/lib.rs").send()?;
+            assert!(response.status().is_success());
+            let page = response.text()?;
+            assert!(page.contains("<code>fn foo() {}</code>"));
+            assert!(page.contains("application/octet-stream"));
+
+            let response = web.get("/crate/fake/0.1.0/source/logo.png").send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["Content-Type"], "image/png");
+            assert_eq!(response.headers()["X-Docsrs-Type-Mismatch"], "true");
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn generic_mime_types_are_sniffed() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/generated", b"fn bar() {}")
+                .source_file("favicon", b"not an icon")
+                .create()?;
+            let blob = |path: &str, mime: &str, content: &[u8]| Blob {
+                path: format!("sources/fake/0.1.0/{}", path),
+                mime: mime.into(),
+                date_updated: Utc::now(),
+                content: content.to_vec(),
+                compression: None,
+            };
+            env.storage().store_blobs(vec![
+                blob("src/lib.rs", "binary/octet-stream", b"fn foo() {}"),
+                blob("src/generated", "application/octet-stream", b"fn bar() {}"),
+                blob("favicon", "image/x-icon", b"not an icon"),
+            ])?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/src/lib.rs").send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["X-Docsrs-Language"], "rust");
+            assert!(response.text()?.contains("<code>fn foo() {}</code>"));
+
+            let response = web.get("/crate/fake/0.1.0/source/src/generated").send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["X-Docsrs-Is-Text"], "true");
+            assert!(response.text()?.contains("<code>fn bar() {}</code>"));
+
+            // specific types are trusted, even if the contents look like text
+            let response = web.get("/crate/fake/0.1.0/source/favicon").send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["Content-Type"], "image/x-icon");
+            Ok(())
+        })
+    }
+
+    #[test_case("src/lib.rs", "file", true)]
+    #[test_case("src/", "dir", true)]
+    #[test_case("", "dir", true)]
+    #[test_case("src/missing.rs", "missing", false)]
+    fn probe_path(path: &str, kind: &str, exists: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web
+                .get(&format!("/crate/fake/0.1.0/source/{}?probe=1", path))
+                .send()?;
+            assert!(response.status().is_success());
+            assert_eq!(
+                response.json::<serde_json::Value>()?,
+                serde_json::json!({ "exists": exists, "kind": kind }),
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn source_browsing_kill_switch() {
+        wrapper(|env| {
+            let dir = tempfile::tempdir()?;
+            let disable_path = dir.path().join("source-browser.disabled");
+            env.override_config(|config| {
+                config.source_browser_disable_path = disable_path.clone();
+            });
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            std::fs::write(&disable_path, b"")?;
+            for path in &[
+                "/crate/fake/0.1.0/source/",
+                "/crate/fake/0.1.0/source/src/lib.rs",
+            ] {
+                assert_eq!(web.get(path).send()?.status(), 503);
+            }
+
+            std::fs::remove_file(&disable_path)?;
+            assert_success("/crate/fake/0.1.0/source/src/lib.rs", web)?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn cache_headers() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("logo.png", &[0x89, b'P', b'N', b'G', 0])
+                .create()?;
+            let web = env.frontend();
+
+            // the stored bytes never change, the pages around them do when the release is yanked
+            for (path, immutable) in &[
+                ("/crate/fake/0.1.0/source/", false),
+                ("/crate/fake/0.1.0/source/src/lib.rs", false),
+                ("/crate/fake/0.1.0/source/src/lib.rs?raw=1", true),
+                ("/crate/fake/0.1.0/source/logo.png?download=1", true),
+            ] {
+                let response = web.get(*path).send()?;
+                assert!(response.status().is_success());
+                let headers = response.headers();
+                assert_eq!(headers["Cache-Tag"], "fake");
+                assert_eq!(headers["Surrogate-Key"], "fake");
+                let cache_control = headers["Cache-Control"].to_str()?;
+                assert_eq!(
+                    cache_control
+                        .split(", ")
+                        .any(|directive| directive == "immutable"),
+                    *immutable,
+                    "{}",
+                    path
+                );
+                assert_eq!(
+                    cache_control
+                        .split(", ")
+                        .any(|directive| directive == "must-revalidate"),
+                    !*immutable,
+                    "{}",
+                    path
+                );
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn vary_keeps_existing_values() {
+        let vary = |existing: Option<&str>| {
+            let mut response = iron::Response::new();
+            if let Some(existing) = existing {
+                response
+                    .headers
+                    .set_raw("Vary", vec![existing.as_bytes().to_vec()]);
+            }
+            super::add_vary(&mut response, "Accept-Encoding");
+            String::from_utf8(response.headers.get_raw("Vary").unwrap()[0].clone()).unwrap()
+        };
+
+        assert_eq!(vary(None), "Accept-Encoding");
+        assert_eq!(vary(Some("Accept")), "Accept, Accept-Encoding");
+        assert_eq!(
+            vary(Some("Accept,accept-encoding")),
+            "Accept, accept-encoding"
+        );
+        assert_eq!(vary(Some("*")), "*");
+    }
+
+    #[test]
+    fn breadcrumbs() {
+        assert_eq!(
+            super::breadcrumbs("foo", "1.0.0", ""),
+            vec![("foo".to_owned(), "/crate/foo/1.0.0/source/".to_owned())]
+        );
+        assert_eq!(
+            super::breadcrumbs("foo", "1.0.0+build", "src/net tcp/"),
+            vec![
+                (
+                    "foo".to_owned(),
+                    "/crate/foo/1.0.0%2Bbuild/source/".to_owned()
+                ),
+                (
+                    "src".to_owned(),
+                    "/crate/foo/1.0.0%2Bbuild/source/src/".to_owned()
+                ),
+                (
+                    "net tcp".to_owned(),
+                    "/crate/foo/1.0.0%2Bbuild/source/src/net%20tcp/".to_owned()
+                ),
+            ]
+        );
+
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/net/tcp/mod.rs", b"")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/net/tcp/mod.rs")
+                .send()?
+                .text()?;
+            for href in &[
+                "/crate/fake/0.1.0/source/",
+                "/crate/fake/0.1.0/source/src/",
+                "/crate/fake/0.1.0/source/src/net/",
+                "/crate/fake/0.1.0/source/src/net/tcp/",
+            ] {
+                assert!(page.contains(&format!(r#"<a href="{}" class="breadcrumb">"#, href)));
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn large_files_are_truncated() {
+        wrapper(|env| {
+            env.override_config(|config| config.source_inline_size_limit = 20);
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("README.md", "# crate\n\nnaïve café\n".as_bytes())
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/README.md")
+                .send()?
+                .text()?;
+            assert!(page.contains("its content is truncated"));
+            assert!(page.contains(r#"<a href="?raw=1">View raw</a>"#));
+            // the limit falls into the `é`, which is left out as a whole
+            assert!(page.contains("naïve caf"));
+            assert!(!page.contains("café"));
+            // the whole file is still counted
+            assert!(page.contains("3 lines, 22 B"));
+
+            let raw = web
+                .get("/crate/fake/0.1.0/source/README.md?raw=1")
+                .send()?
+                .text()?;
+            assert_eq!(raw, "# crate\n\nnaïve café\n");
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(!page.contains("its content is truncated"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn permalink() {
+        assert_eq!(
+            super::permalink("foo", "1.0.0+build", "src/net tcp/mod.rs"),
+            "/crate/foo/1.0.0%2Bbuild/source/src/net%20tcp/mod.rs"
+        );
+        assert_eq!(
+            super::permalink("foo", "1.0.0", "src/"),
+            "/crate/foo/1.0.0/source/src/"
+        );
+        assert_eq!(
+            super::permalink("foo", "1.0.0", ""),
+            "/crate/foo/1.0.0/source/"
+        );
+
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            // semver requirements are resolved before the page is shown, the permalink pins
+            // the release they resolved to
+            for (path, permalink) in &[
+                (
+                    "/crate/fake/*/source/src/lib.rs",
+                    "/crate/fake/0.1.0/source/src/lib.rs",
+                ),
+                ("/crate/fake/*/source/src/", "/crate/fake/0.1.0/source/src/"),
+                ("/crate/fake/0.1/source/", "/crate/fake/0.1.0/source/"),
+            ] {
+                let page = web.get(*path).send()?.text()?;
+                assert!(
+                    page.contains(&format!(r#"<a href="{}" id="copy-permalink""#, permalink)),
+                    "{}",
+                    path
+                );
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn yanked_release_banner() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            env.fake_release()
+                .name("fake")
+                .version("0.2.0")
+                .yanked(true)
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/new.rs", b"fn new() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.2.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains("This release has been yanked."));
+            assert!(page.contains(
+                r#"<a href="/crate/fake/0.1.0/source/src/lib.rs">Go to the latest release, 0.1.0</a>"#
+            ));
+
+            // paths the latest release doesn't have lead to its root
+            let page = web
+                .get("/crate/fake/0.2.0/source/src/new.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"<a href="/crate/fake/0.1.0/source/">"#));
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(!page.contains("This release has been yanked."));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn deleted_release_is_gone() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            env.fake_release()
+                .name("fake")
+                .version("0.2.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            crate::db::delete_version(env, "fake", "0.1.0")?;
+            let web = env.frontend();
+
+            for path in &[
+                "/crate/fake/0.1.0/source/",
+                "/crate/fake/0.1.0/source/src/lib.rs",
+            ] {
+                assert_eq!(web.get(path).send()?.status(), 410, "{}", path);
+            }
+            // releases that never existed are still not found
+            let response = web.get("/crate/fake/0.3.0/source/").send()?;
+            assert_eq!(response.status(), 404);
+            assert_success("/crate/fake/0.2.0/source/", web)?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn line_number_anchors() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}\nfn bar() {}\n")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains(r##"<a href="#L1" id="L1">1</a>"##));
+            assert!(page.contains(r##"<a href="#L2" id="L2">2</a>"##));
+            assert!(!page.contains(r##"href="#L3""##));
+            // `source.js` highlights the lines selected with e.g. `#L1-L2`
+            assert!(page.contains(r#"<pre class="line-numbers">"#));
+            assert!(page.contains("/-/static/source.js"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn line_number_anchors_for_other_text_files() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("Cargo.toml", b"[package]\nname = \"fake\"\n\n")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/Cargo.toml")
+                .send()?
+                .text()?;
+            assert!(page.contains(r##"<a href="#L1" id="L1">1</a>"##));
+            assert!(page.contains(r##"<a href="#L3" id="L3">3</a>"##));
+            assert!(!page.contains(r##"href="#L4""##));
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn server_highlighting(enabled: bool) {
+        wrapper(|env| {
+            env.override_config(|config| config.source_server_highlighting = enabled);
+            let png = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', 0];
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("logo.png", &png)
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert_eq!(
+                page.contains(
+                    r#"<code class="nohighlight"><span class="syntax-source syntax-rust">"#
+                ),
+                enabled
+            );
+            assert_eq!(page.contains("<code>fn foo() {}</code>"), !enabled);
+
+            let fragment = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?fragment=1")
+                .send()?
+                .text()?;
+            assert_eq!(fragment.contains("syntax-rust"), enabled);
+
+            // other files are served as they are
+            let response = web
+                .get("/crate/fake/0.1.0/source/logo.png?download=1")
+                .send()?;
+            assert_eq!(response.bytes()?.as_ref(), png);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn byte_ranges() {
+        for &(range, expected) in &[
+            (None, super::ByteRange::Full),
+            (
+                Some("bytes=0-9"),
+                super::ByteRange::Partial { start: 0, end: 9 },
+            ),
+            (
+                Some("bytes=10-15"),
+                super::ByteRange::Partial { start: 10, end: 15 },
+            ),
+            (
+                Some("bytes=10-1000"),
+                super::ByteRange::Partial { start: 10, end: 99 },
+            ),
+            (
+                Some("bytes=90-"),
+                super::ByteRange::Partial { start: 90, end: 99 },
+            ),
+            (
+                Some("bytes=-10"),
+                super::ByteRange::Partial { start: 90, end: 99 },
+            ),
+            (
+                Some("bytes=-1000"),
+                super::ByteRange::Partial { start: 0, end: 99 },
+            ),
+            (Some("bytes=0-1,5-6"), super::ByteRange::Full),
+            (Some("bytes=100-"), super::ByteRange::Unsatisfiable),
+            (Some("bytes=100-200"), super::ByteRange::Unsatisfiable),
+            (Some("bytes=-0"), super::ByteRange::Unsatisfiable),
+            // malformed ranges are ignored
+            (Some("bytes=10-5"), super::ByteRange::Full),
+            (Some("bytes=abc"), super::ByteRange::Full),
+            (Some("bytes=a-b"), super::ByteRange::Full),
+            (Some("bytes=5"), super::ByteRange::Full),
+            (Some("bytes=-"), super::ByteRange::Full),
+            (Some("items=0-9"), super::ByteRange::Full),
+            (Some(""), super::ByteRange::Full),
+        ] {
+            assert_eq!(super::byte_range(range, 100), expected, "{:?}", range);
+        }
+    }
+
+    #[test]
+    fn range_requests() {
+        wrapper(|env| {
+            // too large for a hexdump, so it's served as it is
+            let content: Vec<u8> = (0..=255).cycle().take(8192).collect();
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("data.bin", &content)
+                .create()?;
+            let web = env.frontend();
+
+            for path in &[
+                "/crate/fake/0.1.0/source/data.bin",
+                "/crate/fake/0.1.0/source/data.bin?raw=1",
+                "/crate/fake/0.1.0/source/data.bin?download=1",
+            ] {
+                let response = web.get(*path).send()?;
+                assert_eq!(response.status(), 200, "{}", path);
+                assert_eq!(response.headers()["Accept-Ranges"], "bytes", "{}", path);
+                assert_eq!(response.bytes()?.as_ref(), &content[..], "{}", path);
+
+                let response = web.get(*path).header("Range", "bytes=100-199").send()?;
+                assert_eq!(response.status(), 206, "{}", path);
+                assert_eq!(
+                    response.headers()["Content-Range"],
+                    "bytes 100-199/8192",
+                    "{}",
+                    path
+                );
+                assert_eq!(response.headers()["Content-Length"], "100", "{}", path);
+                assert_eq!(response.bytes()?.as_ref(), &content[100..200], "{}", path);
+
+                let response = web.get(*path).header("Range", "bytes=-10").send()?;
+                assert_eq!(response.status(), 206, "{}", path);
+                assert_eq!(response.bytes()?.as_ref(), &content[8182..], "{}", path);
+
+                for range in &["bytes=9000-10000", "bytes=8192-"] {
+                    let response = web.get(*path).header("Range", *range).send()?;
+                    assert_eq!(response.status(), 416, "{} {}", path, range);
+                    assert_eq!(
+                        response.headers()["Content-Range"],
+                        "bytes */8192",
+                        "{} {}",
+                        path,
+                        range
+                    );
+                }
+
+                // ranges in other units and malformed ranges are ignored
+                for range in &["items=0-9", "bytes=oops", "bytes=10-5"] {
+                    let response = web.get(*path).header("Range", *range).send()?;
+                    assert_eq!(response.status(), 200, "{} {}", path, range);
+                    assert_eq!(
+                        response.bytes()?.as_ref(),
+                        &content[..],
+                        "{} {}",
+                        path,
+                        range
+                    );
+                }
+            }
+
+            // rendered pages aren't served in parts
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .header("Range", "bytes=0-1")
+                .send()?;
+            assert_eq!(response.status(), 200);
+            assert!(response.headers().get("Accept-Ranges").is_none());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn raw_file() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", "// ünïcode\nfn foo() {}\n".as_bytes())
+                .create()?;
+            let web = env.frontend();
+
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?raw=true")
+                .send()?;
+            assert!(response.status().is_success());
+            assert_eq!(
+                response.headers()["Content-Type"],
+                "text/plain; charset=utf-8"
+            );
+            assert_eq!(response.headers()["Content-Disposition"], "inline");
+            assert_eq!(response.text()?, "// ünïcode\nfn foo() {}\n");
+
+            let response = web.get("/crate/fake/0.1.0/source/src/?raw=true").send()?;
+            assert_eq!(response.status(), 404);
+            let response = web.get("/crate/fake/0.1.0/source/?raw=true").send()?;
+            assert_eq!(response.status(), 404);
+            Ok(())
+        })
+    }
+
+    #[test_case(false)]
+    #[test_case(true)]
+    fn large_files_are_served_as_they_are(large_placeholder: bool) {
+        wrapper(|env| {
+            env.override_config(|config| {
+                config.source_server_highlighting = true;
+                if large_placeholder {
+                    config.source_placeholders.push("large".into());
+                }
+            });
+            let line = "fn foo() -> u32 { 42 }\n";
+            let content = line.repeat(3 * 1024 * 1024 / line.len());
+            assert!(content.len() > env.config().source_render_threshold);
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/generated.rs", content.as_bytes())
+                .create()?;
+            let web = env.frontend();
+
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/generated.rs")
+                .send()?;
+            assert!(response.status().is_success());
+            if large_placeholder {
+                let page = response.text()?;
+                assert!(page.contains("This file is too large to be shown here"));
+                assert!(page.contains(r#"href="./generated.rs?raw=1""#));
+                assert!(!page.contains("fn foo() -&gt; u32"));
+                assert!(!page.contains("syntax-rust"));
+            } else {
+                // served as it is, without being highlighted
+                assert_eq!(response.headers()["X-Docsrs-Is-Text"], "true");
+                assert!(response.text()? == content);
+            }
+
+            for query in &["raw=1", "download=1"] {
+                let response = web
+                    .get(&format!(
+                        "/crate/fake/0.1.0/source/src/generated.rs?{}",
+                        query
+                    ))
+                    .send()?;
+                assert!(response.status().is_success());
+                assert!(response.text()? == content, "{}", query);
+            }
+
+            // smaller files are still rendered
+            let response = web.get("/crate/fake/0.1.0/source/src/lib.rs").send()?;
+            assert!(response.text()?.contains("syntax-rust"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn raw_files_have_canonical_content_types() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("Cargo.toml", b"[package]")
+                .source_file("Cargo.lock", b"version = 3")
+                .source_file("README.md", b"# fake")
+                .source_file("data.json", b"{}")
+                .create()?;
+            let web = env.frontend();
+
+            for (path, mime) in &[
+                ("Cargo.toml", "text/plain; charset=utf-8"),
+                ("Cargo.lock", "text/plain; charset=utf-8"),
+                ("README.md", "text/markdown; charset=utf-8"),
+                ("data.json", "application/json"),
+            ] {
+                let response = web
+                    .get(&format!("/crate/fake/0.1.0/source/{}?raw=true", path))
+                    .send()?;
+                assert_eq!(response.headers()["Content-Type"], *mime, "{}", path);
+            }
+
+            let response = web
+                .get("/crate/fake/0.1.0/source/Cargo.toml?download=1")
+                .send()?;
+            assert!(response.headers()["Content-Type"]
+                .to_str()?
+                .starts_with("text/plain"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn scripts_are_plain_text() {
+        wrapper(|env| {
+            env.override_config(|config| config.source_server_highlighting = true);
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("install.sh", b"#!/bin/sh\necho hi\n")
+                .source_file("build.rs", b"fn main() {}")
+                .create()?;
+            let web = env.frontend();
+
+            for path in &["install.sh", "install.sh?lang=bash"] {
+                let page = web
+                    .get(&format!("/crate/fake/0.1.0/source/{}", path))
+                    .send()?
+                    .text()?;
+                assert!(page.contains("This is a script, it's shown as plain text only."));
+                assert!(page.contains(r#"<code class="language-plaintext">#!/bin/sh"#));
+                assert!(!page.contains("syntax-"));
+            }
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/build.rs")
+                .send()?
+                .text()?;
+            assert!(!page.contains("This is a script"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn fragment_contains_only_the_file() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}\nfn bar() {}\n")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?fragment=1")
+                .send()?;
+            assert!(response.status().is_success());
+            let fragment = response.text()?;
+            assert!(fragment.starts_with(r#"<div class="source-lines">"#));
+            assert!(fragment.contains(r##"<a href="#L1" id="L1">1</a>"##));
+            assert!(fragment.contains(r##"<a href="#L2" id="L2">2</a>"##));
+            assert!(fragment.contains(r#"<code class="language-rust">fn foo() {}"#));
+            assert!(!fragment.contains("<html"));
+            assert!(!fragment.contains("nav-container"));
+
+            // directories have no rendered contents
+            let response = web.get("/crate/fake/0.1.0/source/src/?fragment=1").send()?;
+            assert_eq!(response.status(), 404);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn semver_redirect_encodes_build_metadata() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("1.0.0+build.1")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            assert_redirect(
+                "/crate/fake/1/source/src/lib.rs",
+                "/crate/fake/1.0.0%2Bbuild.1/source/src/lib.rs",
+                web,
+            )?;
+            assert_redirect(
+                "/crate/fake/1/source-files.json?path=src/lib.rs",
+                "/crate/fake/1.0.0%2Bbuild.1/source-files.json?path=src/lib.rs",
+                web,
+            )?;
+
+            assert_eq!(
+                super::encode_path_segment("1.0.0+build/1 2"),
+                "1.0.0%2Bbuild%2F1%202"
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn encoded_build_metadata_version() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("1.0.0+build")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            for path in &[
+                "/crate/foo/1.0.0%2Bbuild/source/",
+                "/crate/foo/1.0.0+build/source/",
+                "/crate/foo/1.0.0%2bbuild/source/src/lib.rs",
+            ] {
+                let response = web.get(path).send()?;
+                assert!(response.status().is_success(), "{}", path);
+            }
+            assert_redirect(
+                "/crate/foo/1.0.0%2Bbuild%20/source/",
+                "/crate/foo/1.0.0%2Bbuild/source/",
+                web,
+            )?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn nosniff_header() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("logo.png", &[0x89, b'P', b'N', b'G', 0])
+                .create()?;
+            let web = env.frontend();
+
+            for path in &[
+                "/crate/fake/0.1.0/source/",
+                "/crate/fake/0.1.0/source/src/lib.rs",
+                "/crate/fake/0.1.0/source/logo.png",
+                "/crate/fake/0.1.0/source-files.json?path=src/lib.rs",
+            ] {
+                let response = web.get(path).send()?;
+                assert!(response.status().is_success());
+                assert_eq!(response.headers()["X-Content-Type-Options"], "nosniff");
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn forced_language() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("snippet.txt", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web
+                .get("/crate/fake/0.1.0/source/snippet.txt?lang=rust")
+                .send()?;
+            assert_eq!(response.headers()["X-Docsrs-Language"], "rust");
+            assert!(response
+                .text()?
+                .contains(r#"<code class="language-rust">fn foo() {}</code>"#));
+
+            // python is known, but its grammar isn't loaded by the page
+            for lang in &["nonsense", "python"] {
+                let response = web
+                    .get(&format!(
+                        "/crate/fake/0.1.0/source/snippet.txt?lang={}",
+                        lang
+                    ))
+                    .send()?;
+                assert!(response.headers().get("X-Docsrs-Language").is_none());
+                assert!(response.text()?.contains("<code>fn foo() {}</code>"));
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn file_as_json() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("logo.png", &[0x89, b'P', b'N', b'G', 0, 0, 0xff, 0xfe])
+                .create()?;
+            let web = env.frontend();
+
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?format=json")
+                .send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["Content-Type"], "application/json");
+            let file = response.json::<serde_json::Value>()?;
+            assert_eq!(file["path"], "src/lib.rs");
+            assert_eq!(file["mime"], "text/rust");
+            assert_eq!(file["is_rust_source"], true);
+            assert_eq!(file["size"], 11);
+            assert_eq!(file["line_count"], 1);
+            assert_eq!(file["content"], "fn foo() {}");
+            assert!(file.get("download_url").is_none());
+
+            // only the requested fields are returned, unknown ones are ignored
+            let file = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?format=json&fields=size,line_count,oops")
+                .send()?
+                .json::<serde_json::Value>()?;
+            assert_eq!(file, serde_json::json!({ "size": 11, "line_count": 1 }));
+
+            let file = web
+                .get("/crate/fake/0.1.0/source/logo.png")
+                .header("Accept", "application/json")
+                .send()?
+                .json::<serde_json::Value>()?;
+            assert_eq!(file["path"], "logo.png");
+            assert_eq!(file["mime"], "image/png");
+            assert_eq!(file["is_rust_source"], false);
+            assert_eq!(file["size"], 8);
+            assert!(file["content"].is_null());
+            let download_url = file["download_url"].as_str().unwrap();
+            assert!(
+                download_url.ends_with("/crate/fake/0.1.0/source/logo.png?download=1"),
+                "{}",
+                download_url
+            );
+
+            let response = web
+                .get("/crate/fake/0.1.0/source/logo.png?download=1")
+                .send()?;
+            assert!(response.status().is_success());
+            assert_eq!(
+                response.bytes()?.as_ref(),
+                &[0x89, b'P', b'N', b'G', 0, 0, 0xff, 0xfe][..]
+            );
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn files_missing_from_the_file_list_are_not_found(archive_storage: bool) {
+        wrapper(|env| {
+            let id = env
+                .fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/orphan.rs", b"fn bar() {}")
+                .create()?;
+            env.db().conn().execute(
+                r#"UPDATE releases SET files = '[["text/rust", "src/lib.rs"]]' WHERE id = $1"#,
+                &[&id],
+            )?;
+            let web = env.frontend();
+
+            assert_success("/crate/fake/0.1.0/source/src/lib.rs", web)?;
+            for path in &[
+                "/crate/fake/0.1.0/source/src/orphan.rs",
+                "/crate/fake/0.1.0/source/src/orphan.rs?raw=true",
+            ] {
+                assert_eq!(web.get(path).send()?.status(), 404, "{}", path);
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn whitespace_only_file() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("blank.txt", b"  \n\t\n")
+                .source_file("notes.txt", b"some notes")
+                .create()?;
+            let web = env.frontend();
+
+            let note = "(file contains only whitespace)";
+            let page = web
+                .get("/crate/fake/0.1.0/source/blank.txt")
+                .send()?
+                .text()?;
+            assert!(page.contains(note));
+            assert!(page.contains("<code>  \n\t\n</code>"));
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/notes.txt")
+                .send()?
+                .text()?;
+            assert!(!page.contains(note));
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn debug_files_json(enabled: bool) {
+        wrapper(|env| {
+            env.override_config(|config| config.source_browser_debug = enabled);
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+
+            let response = env
+                .frontend()
+                .get("/crate/fake/0.1.0/source/?debug=files-json")
+                .send()?;
+            assert!(response.status().is_success());
+            if enabled {
+                assert_eq!(response.headers()["Content-Type"], "application/json");
+                let files = response.json::<serde_json::Value>()?;
+                assert!(files.as_array().unwrap().contains(&serde_json::json!([
+                    "text/rust",
+                    "src/lib.rs",
+                    11
+                ])));
+            } else {
+                assert!(response.text()?.contains("<!DOCTYPE html>"));
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn file_requested_as_directory_redirects() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/c#.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            assert_redirect(
+                "/crate/fake/0.1.0/source/src/lib.rs/",
+                "/crate/fake/0.1.0/source/src/lib.rs",
+                web,
+            )?;
+            assert_redirect(
+                "/crate/fake/0.1.0/source/src/c%23.rs/",
+                "/crate/fake/0.1.0/source/src/c%23.rs",
+                web,
+            )?;
+            assert_redirect(
+                "/crate/fake/0.1.0/source/src/lib.rs/?lang=rust",
+                "/crate/fake/0.1.0/source/src/lib.rs?lang=rust",
+                web,
+            )?;
+            assert_not_found("/crate/fake/0.1.0/source/src/missing.rs/", web)?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn foldable_doc_comments_are_marked() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file(
+                    "src/lib.rs",
+                    b"/// Does foo.\n///\n/// Really.\nfn foo() {}\n",
+                )
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(page
+                .contains(r##"<a href="#L1" id="L1" class="foldable" data-fold-end="3">1</a>"##));
+            assert!(page.contains(r##"<a href="#L4" id="L4">4</a>"##));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn line_query_parameter() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}\nfn bar() {}\nfn baz() {}\n")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?line=2")
+                .send()?
+                .text()?;
+            assert!(page.contains(r##"<a href="#L2" id="L2" class="line-highlighted">2</a>"##));
+            assert!(page.contains(r##"<a href="#L1" id="L1">1</a>"##));
+            // the line of the code is highlighted as well, not only its number
+            assert!(page.contains(r#"<span id="LC2" class="line-highlighted"> </span>"#));
+            assert!(page.contains(r#"<span id="LC1"> </span>"#));
+
+            // lines past the end are clamped to the last line
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?line=999")
+                .send()?;
+            assert!(response.status().is_success());
+            assert!(response
+                .text()?
+                .contains(r##"<a href="#L3" id="L3" class="line-highlighted">3</a>"##));
+
+            // unless they're asked to be rejected
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?line=999&strict=1")
+                .send()?;
+            assert_eq!(response.status(), 404);
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?line=3&strict=1")
+                .send()?;
+            assert!(response.status().is_success());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn invalid_utf8_path() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/src/%FF%FE.rs").send()?;
+            assert_eq!(response.status(), 400);
+            Ok(())
+        })
+    }
+
+    #[test_case("src/lib.rs", None)]
+    #[test_case("src/..rs", None)]
+    #[test_case("../etc/passwd", Some("`..`"))]
+    #[test_case("src/../../other/0.1.0/secret.rs", Some("`..`"))]
+    #[test_case("src\\..\\secret.rs", Some("`..`"))]
+    #[test_case("/etc/passwd", Some("relative"))]
+    #[test_case("src/lib.rs\0.png", Some("null byte"))]
+    fn invalid_source_paths(path: &str, reason: Option<&str>) {
+        match (super::invalid_source_path(path), reason) {
+            (Some(actual), Some(reason)) => assert!(actual.contains(reason), "{}", actual),
+            (actual, reason) => assert_eq!(actual.is_some(), reason.is_some(), "{}", path),
+        }
+    }
+
+    #[test]
+    fn path_traversal_is_rejected() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            env.fake_release()
+                .name("other")
+                .version("0.1.0")
+                .source_file("secret.rs", b"const SECRET: u32 = 42;")
+                .create()?;
+            let web = env.frontend();
+
+            // the client and the router resolve literal and `%2e%2e` dot segments, so these
+            // never reach the source browser with a `..` in them
+            for path in &[
+                "/crate/fake/0.1.0/source/../../etc/passwd",
+                "/crate/fake/0.1.0/source/%2e%2e/%2e%2e/etc/passwd",
+                "/crate/fake/0.1.0/source/src/../../../other/0.1.0/source/secret.rs",
+            ] {
+                let response = web.get(path).send()?;
+                let body = response.text()?;
+                assert!(!body.contains("root:"), "{}", path);
+                assert!(!body.contains("SECRET"), "{}", path);
+            }
+
+            // encoded slashes keep the `..` inside a single segment, which only the source
+            // browser can catch after decoding it
+            for path in &[
+                "/crate/fake/0.1.0/source/..%2F..%2Fetc%2Fpasswd",
+                "/crate/fake/0.1.0/source/..%2f..%2fetc%2fpasswd",
+                "/crate/fake/0.1.0/source/src/..%2F..%2F..%2Fother%2F0.1.0%2Fsecret.rs",
+                "/crate/fake/0.1.0/source/%2e%2e%2F%2e%2e%2Fetc%2Fpasswd",
+                "/crate/fake/0.1.0/source/..%5C..%5Cetc%5Cpasswd",
+                "/crate/fake/0.1.0/source/%2Fetc%2Fpasswd",
+                "/crate/fake/0.1.0/source/src/lib.rs%00",
+            ] {
+                let response = web.get(path).send()?;
+                assert_eq!(response.status(), 400, "{}", path);
+                assert!(!response.text()?.contains("SECRET"), "{}", path);
+            }
+
+            let response = web
+                .get("/crate/other/source-diff?from=0.1.0&to=0.1.0&path=..%2Fother%2Fsecret.rs")
+                .send()?;
+            assert_eq!(response.status(), 400);
+
+            assert_success("/crate/fake/0.1.0/source/src/lib.rs", web)?;
+            Ok(())
+        })
+    }
+
+    #[test_case(None, Some("html"))]
+    #[test_case(Some(&["rs", "md"][..]), None)]
+    fn rendered_extensions_allowlist(allowed: Option<&[&str]>, language: Option<&str>) {
+        wrapper(|env| {
+            env.override_config(|config| {
+                config.source_rendered_extensions =
+                    allowed.map(|allowed| allowed.iter().map(|ext| ext.to_string()).collect());
+            });
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("index.html", b"<p>hello</p>")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/index.html").send()?;
+            assert_eq!(
+                response
+                    .headers()
+                    .get("X-Docsrs-Language")
+                    .map(|language| language.to_str().unwrap()),
+                language
+            );
+            let page = response.text()?;
+            assert_eq!(
+                page.contains(r#"<code class="language-plaintext">"#),
+                language.is_none()
+            );
+            assert!(page.contains("&lt;p&gt;hello"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn server_timing_header() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/src/lib.rs").send()?;
+            assert!(response.status().is_success());
+            let timing = response.headers()["Server-Timing"].to_str()?;
+            let steps: Vec<&str> = timing
+                .split(", ")
+                .map(|step| step.split(";dur=").next().unwrap())
+                .collect();
+            assert_eq!(steps, ["db", "storage", "render"]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn semver_redirect_notes_resolved_version() {
+        wrapper(|env| {
+            for version in &["1.0.0", "1.1.0", "1.2.0", "2.0.0"] {
+                env.fake_release()
+                    .name("fake")
+                    .version(version)
+                    .source_file("src/lib.rs", b"fn foo() {}")
+                    .create()?;
+            }
+            let web = env.override_frontend(|frontend| {
+                use reqwest::blocking::Client;
+                use reqwest::redirect::Policy;
+                frontend.client = Client::builder().redirect(Policy::none()).build().unwrap();
+            });
+
+            let response = web.get("/crate/fake/%5E1.1/source/src/lib.rs").send()?;
+            assert_eq!(response.status(), 302);
+            assert_eq!(response.headers()["X-Docsrs-Version-Spec"], "^1.1");
+            assert_eq!(response.headers()["X-Docsrs-Resolved-Version"], "1.2.0");
+            assert!(response.headers()["Location"]
+                .to_str()?
+                .ends_with("/crate/fake/1.2.0/source/src/lib.rs"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn corrected_name_and_semver_redirect_in_one_hop() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo-bar")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.override_frontend(|frontend| {
+                use reqwest::blocking::Client;
+                use reqwest::redirect::Policy;
+                frontend.client = Client::builder().redirect(Policy::none()).build().unwrap();
+            });
+
+            for path in &[
+                "/crate/foo_bar/*/source/",
+                "/crate/foo_bar/0.1.0/source/",
+                "/crate/foo-bar/*/source/",
+            ] {
+                let response = web.get(path).send()?;
+                assert_eq!(response.status(), 302, "{}", path);
+                assert!(
+                    response.headers()["Location"]
+                        .to_str()?
+                        .ends_with("/crate/foo-bar/0.1.0/source/"),
+                    "{}",
+                    path
+                );
+            }
+
+            let response = web.get("/crate/foo-bar/0.1.0/source/").send()?;
+            assert_eq!(response.status(), 200);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn large_files_are_gzipped_when_accepted() {
+        use std::io::Read;
+
+        wrapper(|env| {
+            let content = "fn foo() {}\n".repeat(1000);
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", content.as_bytes())
+                .source_file("src/tiny.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            for path in &[
+                "/crate/fake/0.1.0/source/src/lib.rs",
+                "/crate/fake/0.1.0/source/src/lib.rs?raw=true",
+            ] {
+                let response = web
+                    .get(path)
+                    .header("Accept-Encoding", "gzip, deflate")
+                    .send()?;
+                assert_eq!(response.status(), 200, "{}", path);
+                assert_eq!(response.headers()["Content-Encoding"], "gzip", "{}", path);
+                assert_eq!(response.headers()["Vary"], "Accept-Encoding", "{}", path);
+
+                let mut body = String::new();
+                flate2::read::GzDecoder::new(&response.bytes()?[..]).read_to_string(&mut body)?;
+                assert!(body.contains("fn foo() {}"), "{}", path);
+            }
+
+            // tiny files and clients that don't support gzip get the plain response
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/tiny.rs?raw=true")
+                .header("Accept-Encoding", "gzip")
+                .send()?;
+            assert!(response.headers().get("Content-Encoding").is_none());
+            for accept_encoding in &["identity", "gzip;q=0"] {
+                let response = web
+                    .get("/crate/fake/0.1.0/source/src/lib.rs?raw=true")
+                    .header("Accept-Encoding", *accept_encoding)
+                    .send()?;
+                assert!(response.headers().get("Content-Encoding").is_none());
+                assert_eq!(response.text()?, content);
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn empty_version_is_rejected() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            for path in &[
+                "/crate/fake/%20/source/",
+                "/crate/fake/%20%09/source/src/lib.rs",
+            ] {
+                assert_eq!(web.get(path).send()?.status(), 400, "{}", path);
+            }
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn small_binary_files_are_previewed(archive_storage: bool) {
+        wrapper(|env| {
+            let large = vec![0; 5 * 1024];
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .archive_storage(archive_storage)
+                .source_file("data.bin", b"\0\x01binary\xff")
+                .source_file("large.bin", &large)
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/data.bin").send()?;
+            assert!(response.status().is_success());
+            assert!(response.headers()["Content-Type"]
+                .to_str()?
+                .starts_with("text/html"));
+            let page = response.text()?;
+            assert!(page.contains(
+                "00000000  00 01 62 69 6e 61 72 79  ff                       |..binary.|"
+            ));
+            assert!(page.contains(r#"href="?download=1""#));
+
+            let response = web
+                .get("/crate/fake/0.1.0/source/data.bin?download=1")
+                .send()?;
+            assert_eq!(response.bytes()?.as_ref(), b"\0\x01binary\xff");
+
+            // larger files are still served as they are
+            let response = web.get("/crate/fake/0.1.0/source/large.bin").send()?;
+            assert_eq!(
+                response.headers()["Content-Type"],
+                "application/octet-stream"
+            );
+            assert_eq!(response.bytes()?.len(), large.len());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn images_are_previewed() {
+        wrapper(|env| {
+            let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+            png.extend_from_slice(&[0, 0, 0, 64, 0, 0, 0, 32]);
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("logo.png", &png)
+                .source_file("logo #2.png", &png)
+                .source_file(
+                    "logo.svg",
+                    br#"<svg xmlns="http://www.w3.org/2000/svg"><script>alert(1)</script><circle r="4"/></svg>"#,
+                )
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/logo.png").send()?;
+            assert!(response.status().is_success());
+            assert!(response.headers()["Content-Type"]
+                .to_str()?
+                .starts_with("text/html"));
+            assert_eq!(response.headers()["X-Docsrs-Is-Text"], "false");
+            let page = response.text()?;
+            assert!(page.contains(r#"<div class="image-preview">"#));
+            assert!(page.contains(r#"?raw=1" alt="logo.png">"#));
+            assert!(page.contains("64 × 32 pixels"));
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/logo%20%232.png")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"<img src="./logo%20%232.png?raw=1" alt="logo #2.png">"#));
+
+            // the image itself is still served as it is
+            let response = web.get("/crate/fake/0.1.0/source/logo.png?raw=1").send()?;
+            assert_eq!(response.headers()["Content-Type"], "image/png");
+            assert_eq!(response.bytes()?.as_ref(), png.as_slice());
+
+            // SVGs are linked to like any other image, never embedded into the page
+            let page = web
+                .get("/crate/fake/0.1.0/source/logo.svg")
+                .send()?
+                .text()?;
+            assert!(page.contains(r#"<img src="./logo.svg?raw=1" alt="logo.svg">"#));
+            assert!(!page.contains("<circle"));
+            assert!(!page.contains("alert(1)"));
+            let response = web.get("/crate/fake/0.1.0/source/logo.svg?raw=1").send()?;
+            assert_eq!(response.headers()["Content-Type"], "image/svg+xml");
+            assert!(response.headers()["Content-Security-Policy"]
+                .to_str()?
+                .starts_with("default-src 'none'"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn source_files_are_cached() {
+        wrapper(|env| {
+            env.override_config(|config| config.source_file_cache_capacity = 100);
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+            let metrics = env.metrics();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains("fn foo() {}"));
+            let misses = metrics.source_file_cache_misses.get();
+            assert!(misses > 0);
+
+            // a changed file in storage goes unnoticed, the cached one is served
+            env.storage().store_blobs(vec![Blob {
+                path: "sources/fake/0.1.0/src/lib.rs".into(),
+                mime: "text/rust".into(),
+                date_updated: Utc::now(),
+                content: b"fn bar() {}".to_vec(),
+                compression: None,
+            }])?;
+            let hits = metrics.source_file_cache_hits.get();
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains("fn foo() {}"));
+            assert_eq!(metrics.source_file_cache_misses.get(), misses);
+            assert!(metrics.source_file_cache_hits.get() > hits);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn etags() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file("src/main.rs", b"fn main() {}")
+                .create()?;
+            let web = env.frontend();
+
+            for path in &[
+                "/crate/fake/0.1.0/source/src/lib.rs",
+                "/crate/fake/0.1.0/source/src/lib.rs?raw=true",
+                "/crate/fake/0.1.0/source/src/",
+            ] {
+                let response = web.get(path).send()?;
+                assert_eq!(response.status(), 200, "{}", path);
+                let etag = response.headers()["ETag"].to_str()?.to_owned();
+                assert!(etag.starts_with('"') && etag.ends_with('"'), "{}", path);
+
+                let response = web.get(path).header("If-None-Match", &etag).send()?;
+                assert_eq!(response.status(), 304, "{}", path);
+                assert_eq!(response.headers()["ETag"], &*etag);
+                assert!(response.bytes()?.is_empty());
+
+                // the tag of another response or a stale one doesn't match
+                let response = web
+                    .get("/crate/fake/0.1.0/source/src/main.rs")
+                    .header("If-None-Match", &etag)
+                    .send()?;
+                assert_eq!(response.status(), 200);
+                let response = web.get(path).header("If-None-Match", r#""stale""#).send()?;
+                assert_eq!(response.status(), 200);
+            }
+
+            // the same file with other parameters is another response
+            let response = web.get("/crate/fake/0.1.0/source/src/lib.rs").send()?;
+            let etag = response.headers()["ETag"].to_str()?.to_owned();
+            let response = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?raw=true")
+                .header("If-None-Match", &etag)
+                .send()?;
+            assert_eq!(response.status(), 200);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn etags_change_when_yanking() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let path = "/crate/fake/0.1.0/source/src/lib.rs";
+            let etag = web.get(path).send()?.headers()["ETag"].to_str()?.to_owned();
+
+            // yanking the release adds a banner to its pages
+            env.db().conn().execute(
+                "UPDATE releases SET yanked = TRUE
+                FROM crates
+                WHERE crates.id = releases.crate_id AND crates.name = 'fake'",
+                &[],
+            )?;
+            let response = web.get(path).header("If-None-Match", &etag).send()?;
+            assert_eq!(response.status(), 200);
+            assert_ne!(response.headers()["ETag"], &*etag);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn syntax_check_badge() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"pub mod truncated;\nfn foo() {}\n")
+                .source_file("src/truncated.rs", b"fn foo() {\n    let x = 1;\n")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs?check=1")
+                .send()?
+                .text()?;
+            assert!(page.contains("Parses OK"));
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/truncated.rs?check=1")
+                .send()?
+                .text()?;
+            assert!(page.contains("Parse error at line 2"));
+            // the file is still shown
+            assert!(page.contains("let x = 1;"));
+
+            // the check is opt-in
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert!(!page.contains("Parses OK"));
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn percent_encoded_paths(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("my file.rs", b"fn foo() {}")
+                .source_file("some dir/lib.rs", b"fn bar() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/my%20file.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains("<code>fn foo() {}</code>"));
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/some%20dir/lib.rs")
+                .send()?
+                .text()?;
+            assert!(page.contains("<code>fn bar() {}</code>"));
+            assert_success("/crate/fake/0.1.0/source/some%20dir/", web)?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn short_urls() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            assert_redirect(
+                "/-/s/fake/0.1.0/src/lib.rs",
+                "/crate/fake/0.1.0/source/src/lib.rs",
+                web,
+            )?;
+            assert_redirect(
+                "/-/s/fake/0.1.0/src/lib.rs?lang=rust",
+                "/crate/fake/0.1.0/source/src/lib.rs?lang=rust",
+                web,
+            )?;
+            assert_redirect("/-/s/fake/0.1.0", "/crate/fake/0.1.0/source/", web)?;
+            assert_redirect("/-/s/fake/0.1.0/", "/crate/fake/0.1.0/source/", web)?;
+            // the canonical URL resolves the version
+            assert_redirect(
+                "/-/s/fake/0.1/src/lib.rs",
+                "/crate/fake/0.1.0/source/src/lib.rs",
+                web,
+            )?;
+
+            // the aliases are below `/-/`, as `/s/` would take over the docs of a crate named `s`
+            env.fake_release()
+                .name("s")
+                .version("0.1.0")
+                .rustdoc_file("s/index.html")
+                .create()?;
+            assert_success("/s/0.1.0/s/", web)?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn issue_link() {
+        assert_eq!(
+            super::issue_link(
+                "{repository}/issues?q=is%3Aissue+{path}",
+                "https://github.com/foo/bar/",
+                "src/my file.rs"
+            ),
+            "https://github.com/foo/bar/issues?q=is%3Aissue+src%2Fmy+file.rs"
+        );
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn issue_link_is_configurable(configured: bool) {
+        wrapper(|env| {
+            if configured {
+                env.override_config(|config| {
+                    config.source_issue_link_template = Some("{repository}/issues?q={path}".into())
+                });
+            }
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/src/lib.rs")
+                .send()?
+                .text()?;
+            assert_eq!(page.contains("issues?q=src%2Flib.rs"), configured);
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn file_is_shown_without_listing(archive_storage: bool) {
+        wrapper(|env| {
+            let id = env
+                .fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .create()?;
+            env.db()
+                .conn()
+                .execute("UPDATE releases SET files = '[]' WHERE id = $1", &[&id])?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/src/lib.rs").send()?;
+            assert!(response.status().is_success());
+            let page = response.text()?;
+            assert!(page.contains("<code>fn foo() {}</code>"));
+            assert!(!page.contains("This directory is empty"));
+
+            assert_not_found("/crate/fake/0.1.0/source/src/", web)?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn oversized_file_placeholder() {
+        wrapper(|env| {
+            env.override_config(|config| config.max_file_size = 16);
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("big.txt", &[b'a'; 64])
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/big.txt").send()?;
+            assert!(response.status().is_success());
+            assert!(response.headers()["Content-Type"]
+                .to_str()?
+                .starts_with("text/html"));
+            let page = response.text()?;
+            assert!(page.contains(r#"<div class="source-placeholder">"#));
+            assert!(page.contains("This file is too large to be shown."));
+            assert!(!page.contains("?download=1"));
+            Ok(())
+        })
+    }
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn binary_file_placeholder(enabled: bool) {
+        wrapper(|env| {
+            if enabled {
+                env.override_config(|config| {
+                    config.source_placeholders.push("binary".into());
+                });
+            }
+            // too large to be shown as a hexdump
+            let content = vec![0u8; super::content::MAX_HEXDUMP_SIZE + 1];
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("data.bin", &content)
+                .source_file("data #2.bin", &content)
+                .create()?;
+            let web = env.frontend();
+
+            let response = web.get("/crate/fake/0.1.0/source/data.bin").send()?;
+            assert!(response.status().is_success());
+            if enabled {
+                let page = response.text()?;
+                assert!(page.contains("This is a binary file, its contents can't be shown."));
+                assert!(page.contains(r#"href="./data.bin?download=1""#));
+
+                let page = web
+                    .get("/crate/fake/0.1.0/source/data%20%232.bin")
+                    .send()?
+                    .text()?;
+                assert!(page.contains(r#"href="./data%20%232.bin?download=1""#));
+            } else {
+                assert_eq!(
+                    response.headers()["Content-Type"],
+                    "application/octet-stream"
+                );
+            }
+
+            let response = web
+                .get("/crate/fake/0.1.0/source/data.bin?download=1")
+                .send()?;
+            assert_eq!(
+                response.headers()["Content-Type"],
+                "application/octet-stream"
+            );
+            assert_eq!(
+                response.headers()["Content-Disposition"],
+                r#"attachment; filename="data.bin""#
+            );
+            assert_eq!(response.bytes()?.as_ref(), content.as_slice());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn non_utf8_text_placeholder() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("broken.txt", b"caf\xe9\0")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/broken.txt")
+                .send()?
+                .text()?;
+            assert!(page.contains("This file isn't valid UTF-8"));
+            assert!(page.contains(r#"href="./broken.txt?download=1""#));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn non_utf8_text_is_transcoded() {
+        wrapper(|env| {
+            env.override_config(|config| config.source_placeholders.clear());
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("latin1.txt", b"caf\xe9")
+                .source_file("utf16.txt", b"\xff\xfec\0a\0f\0\xe9\0")
+                .source_file("broken.txt", b"caf\xe9\0")
+                .create()?;
+            let web = env.frontend();
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/latin1.txt")
+                .send()?
+                .text()?;
+            assert!(page.contains("<code>café</code>"));
+            assert!(page.contains("This file is encoded as windows-1252"));
+
+            let page = web
+                .get("/crate/fake/0.1.0/source/utf16.txt")
+                .send()?
+                .text()?;
+            assert!(page.contains("<code>café</code>"));
+            assert!(page.contains("This file is encoded as UTF-16LE"));
+
+            // text that can't be decoded is served as it is
+            let response = web.get("/crate/fake/0.1.0/source/broken.txt").send()?;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers()["X-Docsrs-Is-Text"], "false");
+            assert_eq!(response.bytes()?.as_ref(), b"caf\xe9\0");
+            Ok(())
+        })
+    }
+}
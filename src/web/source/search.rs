@@ -0,0 +1,211 @@
+//! Searching the source files of a release
+
+use crate::Storage;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Maximum number of files read for a single `?search=`
+const MAX_SEARCH_FILES: usize = 500;
+/// Matching lines longer than this are cut off in the returned snippet
+const MAX_SNIPPET_LENGTH: usize = 200;
+
+/// A line of a source file containing the searched text
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct SearchMatch {
+    path: String,
+    /// The 1-based number of the line
+    line: usize,
+    snippet: String,
+}
+
+/// The result of searching the files in a directory of a release for some text
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct SearchResults {
+    matches: Vec<SearchMatch>,
+    /// The number of matches in the searched files, including the ones that weren't returned
+    total_matches: usize,
+    /// Whether files weren't searched or matches weren't returned because of the limits
+    truncated: bool,
+    /// The number of files that couldn't be fetched from the storage and weren't searched
+    skipped_files: usize,
+}
+
+/// Searches the text files in `dir` and its subdirectories for lines containing `query`.
+///
+/// At most `MAX_SEARCH_FILES` files are read and `max_matches` matches returned, see
+/// `Config::source_search_max_matches`. Files that can't be read as UTF-8 text are skipped, and
+/// so are files that can't be fetched, which are counted in `skipped_files`.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn search_files(
+    storage: &Storage,
+    name: &str,
+    version: &str,
+    archive_storage: bool,
+    files: &Value,
+    dir: &str,
+    query: &str,
+    max_matches: usize,
+) -> SearchResults {
+    let mut text_files: Vec<_> = files
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|file| {
+            file.get(0)
+                .and_then(Value::as_str)
+                .map_or(false, |mime| mime.starts_with("text"))
+        })
+        .filter_map(|file| file.get(1).and_then(Value::as_str))
+        .filter(|path| *path != ".cargo-ok" && path.starts_with(dir))
+        .collect();
+    text_files.sort_unstable();
+
+    let mut results = SearchResults {
+        matches: Vec::new(),
+        total_matches: 0,
+        truncated: text_files.len() > MAX_SEARCH_FILES,
+        skipped_files: 0,
+    };
+    for path in text_files.into_iter().take(MAX_SEARCH_FILES) {
+        let blob = match storage.fetch_source_file(name, version, path, archive_storage) {
+            Ok(blob) => blob,
+            Err(err) => {
+                log::warn!(
+                    "couldn't search '{}' of {} {}: {:?}",
+                    path,
+                    name,
+                    version,
+                    err
+                );
+                results.skipped_files += 1;
+                continue;
+            }
+        };
+        let content = match std::str::from_utf8(&blob.content) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+
+        for (idx, line) in content.lines().enumerate() {
+            if !line.contains(query) {
+                continue;
+            }
+            results.total_matches += 1;
+            if results.matches.len() == max_matches {
+                results.truncated = true;
+                continue;
+            }
+            results.matches.push(SearchMatch {
+                path: path.to_owned(),
+                line: idx + 1,
+                snippet: line.trim().chars().take(MAX_SNIPPET_LENGTH).collect(),
+            });
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::*;
+    use test_case::test_case;
+
+    #[test_case(true)]
+    #[test_case(false)]
+    fn search_source_files(archive_storage: bool) {
+        wrapper(|env| {
+            env.fake_release()
+                .archive_storage(archive_storage)
+                .name("fake")
+                .version("0.1.0")
+                .source_file(
+                    "src/lib.rs",
+                    b"use std::net::TcpStream;\n\nfn foo(_: TcpStream) {}\n",
+                )
+                .source_file("src/net/mod.rs", b"    // TcpStream  \n")
+                .source_file("README.md", b"TcpStream")
+                .source_file("logo.png", b"TcpStream\x89PNG")
+                .create()?;
+            let web = env.frontend();
+
+            let results: serde_json::Value = web
+                .get("/crate/fake/0.1.0/source/src/?search=TcpStream")
+                .send()?
+                .json()?;
+            assert_eq!(
+                results,
+                serde_json::json!({
+                    "matches": [
+                        {"path": "src/lib.rs", "line": 1, "snippet": "use std::net::TcpStream;"},
+                        {"path": "src/lib.rs", "line": 3, "snippet": "fn foo(_: TcpStream) {}"},
+                        {"path": "src/net/mod.rs", "line": 1, "snippet": "// TcpStream"},
+                    ],
+                    "total_matches": 3,
+                    "truncated": false,
+                    "skipped_files": 0,
+                })
+            );
+
+            // binary files aren't searched
+            let results: serde_json::Value = web
+                .get("/crate/fake/0.1.0/source/?search=TcpStream")
+                .send()?
+                .json()?;
+            assert_eq!(results["total_matches"], 4);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn search_results_are_capped() {
+        wrapper(|env| {
+            env.override_config(|config| config.source_search_max_matches = 10);
+            let content = "needle\n".repeat(15);
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", content.as_bytes())
+                .create()?;
+
+            let results: serde_json::Value = env
+                .frontend()
+                .get("/crate/fake/0.1.0/source/?search=needle")
+                .send()?
+                .json()?;
+            assert_eq!(results["matches"].as_array().unwrap().len(), 10);
+            assert_eq!(results["total_matches"], 15);
+            assert_eq!(results["truncated"], true);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn search_skips_files_missing_from_the_storage() {
+        wrapper(|env| {
+            let id = env
+                .fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn needle() {}")
+                .create()?;
+            env.db().conn().execute(
+                r#"UPDATE releases
+                SET files = '[["text/rust", "src/gone.rs"], ["text/rust", "src/lib.rs"]]'
+                WHERE id = $1"#,
+                &[&id],
+            )?;
+
+            let response = env
+                .frontend()
+                .get("/crate/fake/0.1.0/source/?search=needle")
+                .send()?;
+            assert_eq!(response.status(), 200);
+            let results: serde_json::Value = response.json()?;
+            assert_eq!(results["total_matches"], 1);
+            assert_eq!(results["matches"][0]["path"], "src/lib.rs");
+            assert_eq!(results["skipped_files"], 1);
+            Ok(())
+        })
+    }
+}
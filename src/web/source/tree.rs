@@ -0,0 +1,160 @@
+//! The complete source tree of a release as JSON
+
+use super::{resolve_source_release, set_immutable_cache_headers, set_nosniff, SourceRelease};
+use crate::web::error::Nope;
+use iron::{headers::ContentType, status, IronResult, Request, Response};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// An entry of the complete source tree of a release, see `source_tree`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SourceTreeNode {
+    Dir {
+        name: String,
+        children: Vec<SourceTreeNode>,
+    },
+    File {
+        name: String,
+        mime: String,
+    },
+}
+
+/// Builds the nested directory tree of the stored file list of a release. Like in the listing,
+/// directories come first and entries are sorted by name.
+fn source_tree(files: &Value) -> Vec<SourceTreeNode> {
+    #[derive(Default)]
+    struct Dir<'a> {
+        dirs: BTreeMap<&'a str, Dir<'a>>,
+        files: BTreeMap<&'a str, &'a str>,
+    }
+
+    fn into_nodes(dir: Dir<'_>) -> Vec<SourceTreeNode> {
+        let dirs = dir.dirs.into_iter().map(|(name, dir)| SourceTreeNode::Dir {
+            name: name.to_owned(),
+            children: into_nodes(dir),
+        });
+        let files = dir
+            .files
+            .into_iter()
+            .map(|(name, mime)| SourceTreeNode::File {
+                name: name.to_owned(),
+                mime: mime.to_owned(),
+            });
+        dirs.chain(files).collect()
+    }
+
+    let mut root = Dir::default();
+    for file in files.as_array().into_iter().flatten() {
+        let (mime, path) = match (
+            file.get(0).and_then(Value::as_str),
+            file.get(1).and_then(Value::as_str),
+        ) {
+            (Some(mime), Some(path)) => (mime, path),
+            _ => continue,
+        };
+        // skip .cargo-ok generated by cargo
+        if path == ".cargo-ok" {
+            continue;
+        }
+
+        let mut components: Vec<&str> = path.split('/').collect();
+        let name = components.pop().unwrap_or_default();
+        let dir = components.into_iter().fold(&mut root, |dir, component| {
+            dir.dirs.entry(component).or_default()
+        });
+        dir.files.insert(name, mime);
+    }
+    into_nodes(root)
+}
+
+/// Serves the complete source tree of a release as nested JSON, built from the stored file list
+/// alone. `?format=json` is accepted for symmetry with the listings, but JSON is all there is.
+pub fn source_tree_handler(req: &mut Request) -> IronResult<Response> {
+    set_nosniff(resolve_source_release(req, source_tree_response))
+}
+
+fn source_tree_response(req: &Request, name: &str, release: SourceRelease) -> IronResult<Response> {
+    let files = match &release.files {
+        Some(files) => files,
+        None => return Err(Nope::ResourceNotFound.into()),
+    };
+
+    let tree = source_tree(files);
+    let mut resp = Response::with((status::Ok, serde_json::to_string(&tree).unwrap()));
+    resp.headers.set(ContentType::json());
+    // the files of a release never change, so caches can keep its tree
+    set_immutable_cache_headers(&mut resp, name);
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::*;
+
+    #[test]
+    fn source_tree() {
+        let files = serde_json::json!([
+            ["text/rust", "src/net/tcp.rs", 10],
+            ["text/plain", ".cargo-ok"],
+            ["text/toml", "Cargo.toml"],
+            ["text/rust", "src/lib.rs"],
+            ["text/markdown", "README.md"],
+            ["text/rust", "src/net/mod.rs"],
+            ["text/rust", "build.rs"],
+        ]);
+        assert_eq!(
+            serde_json::to_value(super::source_tree(&files)).unwrap(),
+            serde_json::json!([
+                {"type": "dir", "name": "src", "children": [
+                    {"type": "dir", "name": "net", "children": [
+                        {"type": "file", "name": "mod.rs", "mime": "text/rust"},
+                        {"type": "file", "name": "tcp.rs", "mime": "text/rust"},
+                    ]},
+                    {"type": "file", "name": "lib.rs", "mime": "text/rust"},
+                ]},
+                {"type": "file", "name": "Cargo.toml", "mime": "text/toml"},
+                {"type": "file", "name": "README.md", "mime": "text/markdown"},
+                {"type": "file", "name": "build.rs", "mime": "text/rust"},
+            ]),
+        );
+    }
+
+    #[test]
+    fn source_tree_endpoint() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("fake")
+                .version("0.1.0")
+                .source_file("src/lib.rs", b"fn foo() {}")
+                .source_file(".cargo-ok", b"ok")
+                .create()?;
+            let web = env.frontend();
+
+            let response = web
+                .get("/crate/fake/0.1.0/source-tree?format=json")
+                .send()?;
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                response.json::<serde_json::Value>()?,
+                serde_json::json!([
+                    {"type": "dir", "name": "src", "children": [
+                        {"type": "file", "name": "lib.rs", "mime": "text/rust"},
+                    ]},
+                ]),
+            );
+
+            assert_redirect(
+                "/crate/fake/*/source-tree?format=json",
+                "/crate/fake/0.1.0/source-tree?format=json",
+                web,
+            )?;
+            assert_eq!(
+                web.get("/crate/fake/0.2.0/source-tree").send()?.status(),
+                404
+            );
+            Ok(())
+        })
+    }
+}
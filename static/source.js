@@ -30,12 +30,131 @@
         }
     }
 
+    // Parses fragments like `#L15` and `#L10-L20`, in either order, into `[from, to]`
+    function parseLineRange(hash) {
+        var match = /^#L(\d+)(?:-L(\d+))?$/.exec(hash);
+        if (!match) {
+            return null;
+        }
+        var from = parseInt(match[1], 10);
+        var to = match[2] ? parseInt(match[2], 10) : from;
+        return from <= to ? [from, to] : [to, from];
+    }
+
+    function highlightLines(scroll) {
+        var highlighted = document.querySelectorAll(
+            "pre.line-numbers a.line-highlighted, pre.line-overlay span.line-highlighted"
+        );
+        for (var i = 0; i < highlighted.length; ++i) {
+            highlighted[i].classList.remove("line-highlighted");
+        }
+
+        var range = parseLineRange(window.location.hash);
+        if (!range) {
+            return;
+        }
+        for (var line = range[0]; line <= range[1]; ++line) {
+            var number = document.getElementById("L" + line);
+            if (!number) {
+                break;
+            }
+            number.classList.add("line-highlighted");
+            var code = document.getElementById("LC" + line);
+            if (code) {
+                code.classList.add("line-highlighted");
+            }
+        }
+
+        // the browser only scrolls to single lines on its own, since ranges aren't element ids
+        var first = document.getElementById("L" + range[0]);
+        if (scroll && first && range[0] !== range[1]) {
+            first.scrollIntoView();
+        }
+    }
+
+    // Clicking a line number selects that line, shift-clicking extends the selected range
+    function selectLine(event) {
+        var number = event.target.closest("pre.line-numbers a");
+        if (!number) {
+            return;
+        }
+        event.preventDefault();
+
+        var line = parseInt(number.id.slice(1), 10);
+        var range = parseLineRange(window.location.hash);
+        var hash = "#L" + line;
+        if (event.shiftKey && range) {
+            var from = range[0] === line ? range[1] : range[0];
+            hash = from === line ? "#L" + line : "#L" + from + "-L" + line;
+        }
+        history.replaceState(null, "", hash);
+        highlightLines(false);
+    }
+
+    // Copies the link to this exact version to the clipboard, along with the selected lines
+    function copyPermalink(event) {
+        event.preventDefault();
+        var link = event.currentTarget;
+        var label = link.querySelector(".text");
+        navigator.clipboard.writeText(link.href + window.location.hash).then(function() {
+            label.textContent = "Copied!";
+            setTimeout(function() {
+                label.textContent = "Copy permalink";
+            }, 1000);
+        });
+    }
+
+    // `j` and `k` go to the next and previous file, unless they're typed into a form field
+    function goToNeighbor(event) {
+        if (event.ctrlKey || event.altKey || event.metaKey || event.defaultPrevented) {
+            return;
+        }
+        var target = event.target;
+        if (target.isContentEditable || /^(INPUT|TEXTAREA|SELECT)$/.test(target.tagName)) {
+            return;
+        }
+        var link = null;
+        if (event.key === "j") {
+            link = document.getElementById("next-file");
+        } else if (event.key === "k") {
+            link = document.getElementById("prev-file");
+        }
+        if (link) {
+            window.location.href = link.href;
+        }
+    }
+
     document.addEventListener("DOMContentLoaded", function(event) { 
+        var permalink = document.getElementById("copy-permalink");
+        if (permalink) {
+            permalink.addEventListener("click", copyPermalink);
+        }
+
+        // directory listings don't have a file to hide the sidebar for
         var toggleSourceButton = document.querySelector("li.toggle-source button");
-        oldLabel = toggleSourceButton.getAttribute("aria-label");
+        if (toggleSourceButton) {
+            oldLabel = toggleSourceButton.getAttribute("aria-label");
 
-        toggleSourceButton.addEventListener("click", function() {
-            toggleSource(toggleSourceButton);
-        });
+            toggleSourceButton.addEventListener("click", function() {
+                toggleSource(toggleSourceButton);
+            });
+        }
+
+        document.addEventListener("keydown", goToNeighbor);
+
+        var lineNumbers = document.querySelector("pre.line-numbers");
+        if (lineNumbers) {
+            lineNumbers.addEventListener("click", selectLine);
+            window.addEventListener("hashchange", function() {
+                highlightLines(true);
+            });
+            // a line requested with `?line=` is marked by the server, unless a fragment overrides it
+            var target = lineNumbers.querySelector("a.line-highlighted");
+            if (target && !parseLineRange(window.location.hash)) {
+                history.replaceState(null, "", "#" + target.id);
+                target.scrollIntoView();
+            }
+            highlightLines(true);
+        }
     });
 })();